@@ -5,12 +5,24 @@
 
 use std::collections::{BTreeSet, HashMap, HashSet};
 
+#[derive(Deserialize)]
+pub struct PierceSpec {
+    pub enemies: Option<u32>,
+    pub friendlies: Option<bool>,
+    pub falloff: Option<f64>,
+}
+
 #[derive(Deserialize)]
 pub struct RangeSpec {
     pub kind: String,
     pub min: Option<u32>,
     pub max: Option<u32>,
     pub range: Option<u32>,
+    pub metric: Option<String>,
+    pub radius: Option<u32>,
+    pub falloff: Option<f64>,
+    pub friendly_fire: Option<bool>,
+    pub pierce: Option<PierceSpec>,
 }
 
 #[derive(Deserialize)]
@@ -28,6 +40,7 @@ pub struct TerrainSpec {
 
 #[derive(Deserialize)]
 pub struct AttackSpec {
+    pub name: Option<String>,
     pub damage: f64,
     pub range: RangeSpec,
     pub modifiers: HashMap<String, f64>,
@@ -49,26 +62,117 @@ pub struct MovementSpec {
 
 #[derive(Deserialize)]
 pub struct UnitKindSpec {
-    pub attack: AttackSpec,
+    pub attack: Option<AttackSpec>,
+    pub weapons: Option<Vec<AttackSpec>>,
     pub defense: DefenseSpec,
     pub movement: MovementSpec,
     pub capture: Option<u32>,
+    pub first_strike: Option<bool>,
+    pub terrain: Option<HashMap<String, TerrainBonusSpec>>,
+    pub zone_of_control: Option<ZoneOfControlSpec>,
     pub sprite: SpriteSpec,
 }
 
+#[derive(Deserialize)]
+pub struct ZoneOfControlSpec {
+    pub kind: String,
+    pub cost: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct TerrainBonusSpec {
+    pub attack: Option<f64>,
+    pub defense: Option<f64>,
+}
+
+#[derive(Deserialize)]
+pub struct ElevationSpec {
+    pub range: Option<u32>,
+    pub attack: Option<f64>,
+    pub uphill: Option<f64>,
+    pub climb: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct CombatSpec {
+    pub formula: Option<String>,
+    pub ranged_retaliation: Option<bool>,
+    pub scale_retaliation: Option<bool>,
+    pub retaliation: Option<f64>,
+    pub flanking: Option<f64>,
+    pub pincer: Option<f64>,
+    pub support: Option<f64>,
+    pub side_penalty: Option<f64>,
+    pub rear_penalty: Option<f64>,
+}
+
 #[derive(Deserialize)]
 pub struct Spec {
     pub movement_classes: HashMap<String, MovementClassSpec>,
     pub unit_kinds: HashMap<String, UnitKindSpec>,
     pub terrain: HashMap<String, TerrainSpec>,
     pub defense_classes: HashSet<String>,
+    pub elevation: Option<ElevationSpec>,
+    pub combat: Option<CombatSpec>,
 }
 
 pub type LayerSpec = HashMap<String, BTreeSet<(i32, i32, u32)>>;
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct LevelSpec {
     pub name: String,
     pub schema: String,
+    pub topology: Option<String>,
+    pub diagonal_cost: Option<u32>,
+    pub wrap: Option<String>,
+    pub luck: Option<u32>,
+    pub seed: Option<u32>,
     pub layers: HashMap<String, LayerSpec>,
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct ActionSpec {
+    pub kind: String,
+    pub from: (u32, u32),
+    pub to: (u32, u32),
+    pub target: Option<(u32, u32)>,
+    pub weapon: Option<usize>,
+    pub facing: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TurnFileSpec {
+    pub version: Option<u32>,
+    pub turn: u32,
+    pub faction: String,
+    pub state_before: u64,
+    pub actions: Vec<ActionSpec>,
+    pub state_after: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UnitStateSpec {
+    pub pos: (i32, i32),
+    pub health: u32,
+    pub spent: bool,
+    pub goal: Option<(i32, i32)>,
+    pub facing: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CaptureSpec {
+    pub pos: (i32, i32),
+    pub faction: String,
+    pub progress: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveSpec {
+    pub level: LevelSpec,
+    pub units: Vec<UnitStateSpec>,
+    pub captures: Vec<CaptureSpec>,
+    pub factions: Vec<String>,
+    pub current: String,
+    pub turn: u32,
+    pub actions_left: u32,
+}
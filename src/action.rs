@@ -0,0 +1,218 @@
+use spec::ActionSpec;
+
+use common::TurnInfo;
+use grid::Grid;
+
+/// A completed unit action: moving from one tile to another, turning to
+/// face some direction and then doing something at the destination.
+///
+/// Without a `facing`, the unit faces the way it last stepped, as chosen
+/// by the path finder.
+///
+/// Giving or cancelling a go-to order is an action too, but a free one:
+/// the unit stays put and the turn keeps its actions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Attack {
+        from: (u32, u32),
+        to: (u32, u32),
+        facing: Option<usize>,
+        target: (u32, u32),
+        /// The number of the weapon used.
+        weapon: usize,
+    },
+    Capture {
+        from: (u32, u32),
+        to: (u32, u32),
+        facing: Option<usize>,
+    },
+    Wait {
+        from: (u32, u32),
+        to: (u32, u32),
+        facing: Option<usize>,
+    },
+    Order {
+        at: (u32, u32),
+        goal: Option<(u32, u32)>,
+    },
+}
+
+impl Action {
+    pub fn from_spec(spec: ActionSpec) -> Result<Action, String> {
+        let (from, to, facing) = (spec.from, spec.to, spec.facing);
+        Ok(match &spec.kind[..] {
+            "attack" => {
+                Action::Attack {
+                    from: from,
+                    to: to,
+                    facing: facing,
+                    target: spec.target
+                        .ok_or_else(|| "missing field 'target' for attack".to_owned())?,
+                    weapon: spec.weapon.unwrap_or(0),
+                }
+            }
+            "capture" => {
+                Action::Capture {
+                    from: from,
+                    to: to,
+                    facing: facing,
+                }
+            }
+            "wait" => {
+                Action::Wait {
+                    from: from,
+                    to: to,
+                    facing: facing,
+                }
+            }
+            "order" => {
+                Action::Order {
+                    at: from,
+                    goal: spec.target,
+                }
+            }
+            kind => return Err(format!("unrecognized action kind {:?}", kind)),
+        })
+    }
+
+    pub fn to_spec(&self) -> ActionSpec {
+        let (from, to) = self.movement();
+        let (kind, target, weapon) = match *self {
+            Action::Attack { target, weapon, .. } => ("attack", Some(target), Some(weapon)),
+            Action::Capture { .. } => ("capture", None, None),
+            Action::Wait { .. } => ("wait", None, None),
+            Action::Order { goal, .. } => ("order", goal, None),
+        };
+        ActionSpec {
+            kind: kind.to_owned(),
+            from: from,
+            to: to,
+            target: target,
+            weapon: weapon,
+            facing: self.facing(),
+        }
+    }
+
+    /// Returns the origin and destination of the acting unit.
+    #[inline]
+    pub fn movement(&self) -> ((u32, u32), (u32, u32)) {
+        match *self {
+            Action::Attack { from, to, .. } |
+            Action::Capture { from, to, .. } |
+            Action::Wait { from, to, .. } => (from, to),
+            Action::Order { at, .. } => (at, at),
+        }
+    }
+
+    /// Returns the direction the acting unit turns to, if one was chosen.
+    #[inline]
+    pub fn facing(&self) -> Option<usize> {
+        match *self {
+            Action::Attack { facing, .. } |
+            Action::Capture { facing, .. } |
+            Action::Wait { facing, .. } => facing,
+            Action::Order { .. } => None,
+        }
+    }
+
+    /// Checks that the action is legal and performs it.
+    ///
+    /// The grid is left untouched if the action is illegal.
+    pub fn apply(&self, grid: &mut Grid, turn_info: &mut TurnInfo) -> Result<(), String> {
+        let (from, to) = self.movement();
+        let (w, h) = grid.size();
+        for &(x, y) in &[from, to] {
+            if x >= w || y >= h {
+                return Err(format!("position {:?} is outside the map", (x, y)));
+            }
+        }
+        let old_facing = match grid.unit(from) {
+            None => return Err(format!("no unit at {:?}", from)),
+            Some(unit) => {
+                if !turn_info.can_act(unit) {
+                    return Err(format!("unit at {:?} cannot act", from));
+                }
+                unit.facing
+            }
+        };
+        if let Action::Order { at, goal } = *self {
+            if let Some(goal) = goal {
+                if goal.0 >= w || goal.1 >= h {
+                    return Err(format!("position {:?} is outside the map", goal));
+                }
+                if goal == at || grid.route(at, goal).is_none() {
+                    return Err(format!("unit at {:?} cannot go to {:?}", at, goal));
+                }
+            }
+            grid.unit_mut(at).expect("unit vanished").goal = goal;
+            return Ok(());
+        }
+        let mut facing = self.facing();
+        if let Some(facing) = facing {
+            if facing >= grid.topology().direction_count() {
+                return Err(format!("unrecognized facing {}", facing));
+            }
+        }
+        if from != to {
+            if grid.unit(to).is_some() {
+                return Err(format!("cannot move to occupied tile {:?}", to));
+            }
+            let path = match grid.path_finder(from).path(to) {
+                Some(path) => path,
+                None => return Err(format!("unit at {:?} cannot reach {:?}", from, to)),
+            };
+            if facing.is_none() {
+                let last = if path.len() < 2 { from } else { path[path.len() - 2] };
+                facing = grid.heading(last, to);
+            }
+        }
+        let facing = facing.unwrap_or(old_facing);
+
+        // Moves the unit and turns it to face the chosen way.
+        let move_unit = |grid: &mut Grid, from: (u32, u32), to: (u32, u32), facing: usize| {
+            grid.move_unit(from, to);
+            grid.unit_mut(to).expect("unit vanished while moving").facing = facing;
+        };
+
+        match *self {
+            Action::Attack { target, weapon, .. } => {
+                move_unit(grid, from, to, facing);
+                let can_attack = {
+                    let unit = grid.unit(to).expect("unit vanished while moving");
+                    weapon < unit.kind.weapons.len() &&
+                    grid.find_attackable_with(unit, weapon, to, from != to).any(|p| p == target)
+                };
+                if !can_attack {
+                    move_unit(grid, to, from, old_facing);
+                    return Err(format!("unit at {:?} cannot attack {:?} with weapon {}",
+                                       to,
+                                       target,
+                                       weapon));
+                }
+                grid.attack(to, target, weapon);
+            }
+            Action::Capture { .. } => {
+                move_unit(grid, from, to, facing);
+                if !grid.can_capture(to) {
+                    move_unit(grid, to, from, old_facing);
+                    return Err(format!("unit at {:?} cannot capture", to));
+                }
+                grid.capture(to);
+            }
+            Action::Wait { .. } => {
+                move_unit(grid, from, to, facing);
+            }
+            Action::Order { .. } => unreachable!(),
+        }
+
+        if let Some(unit) = grid.unit_mut(to) {
+            unit.spent = true;
+            // A unit following a go-to order is done when it gets there.
+            if unit.goal == Some(to) {
+                unit.goal = None;
+            }
+        }
+        turn_info.spend_action();
+        Ok(())
+    }
+}
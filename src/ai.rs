@@ -0,0 +1,122 @@
+use action::Action;
+use common::TurnInfo;
+use faction::Faction;
+use grid::{Grid, PathPreference};
+
+/// How much capturing a tile is worth, in health points.
+const CAPTURE_SCORE: i64 = 5;
+
+/// The total health of a faction's units minus that of everybody else.
+fn material(grid: &Grid, faction: Faction) -> i64 {
+    grid.units().fold(0, |sum, unit| {
+        if unit.faction == faction {
+            sum + unit.health as i64
+        } else {
+            sum - unit.health as i64
+        }
+    })
+}
+
+/// Picks the next action for the current faction, or `None` if it
+/// should end its turn.
+///
+/// This is a greedy player: every attack and capture is tried on a copy
+/// of the grid and scored by how it changes the balance of health.
+/// Units with nothing better to do move towards the nearest enemy.
+pub fn next_action(grid: &Grid, turn_info: &TurnInfo) -> Option<Action> {
+    if turn_info.actions_left() == 0 {
+        return None;
+    }
+    let faction = turn_info.current_faction();
+
+    let mut units = Vec::new();
+    let mut enemies = Vec::new();
+    let (w, h) = grid.size();
+    for y in 0..h {
+        for x in 0..w {
+            if let Some(unit) = grid.unit((x, y)) {
+                if unit.faction != faction {
+                    enemies.push((x, y));
+                } else if turn_info.can_act(unit) {
+                    units.push((x, y));
+                }
+            }
+        }
+    }
+    let approach = |pos: (u32, u32)| -> i64 {
+        enemies.iter().map(|&e| -(grid.distance(pos, e) as i64)).max().unwrap_or(0)
+    };
+
+    let base = material(grid, faction);
+    let score = |action: &Action| -> Option<i64> {
+        let (from, to) = action.movement();
+        let bonus = match *action {
+            Action::Attack { .. } => 0,
+            Action::Capture { .. } => CAPTURE_SCORE,
+            Action::Wait { .. } => return Some(approach(to) - approach(from)),
+            Action::Order { .. } => return None,
+        };
+        let mut grid = grid.clone();
+        // Plan for average luck rather than peeking at the dice.
+        let dice = *grid.dice();
+        grid.set_luck(0, dice);
+        let mut turn_info = turn_info.clone();
+        match action.apply(&mut grid, &mut turn_info) {
+            Ok(()) => Some(material(&grid, faction) - base + bonus),
+            Err(_) => None,
+        }
+    };
+
+    let mut best: Option<(i64, Action)> = None;
+    for &from in &units {
+        let unit = grid.unit(from).expect("unit vanished while planning");
+        for to in grid.path_finder_with(from, PathPreference::First).reachable() {
+            if to != from && grid.unit(to).is_some() {
+                continue;
+            }
+
+            // Face the nearest enemy, to keep its attacks off the sides.
+            let facing = enemies.iter()
+                .min_by_key(|&&e| grid.distance(to, e))
+                .and_then(|&e| grid.heading(to, e));
+
+            let mut candidates = vec![Action::Wait {
+                                          from: from,
+                                          to: to,
+                                          facing: facing,
+                                      }];
+            for weapon in 0..unit.kind.weapons.len() {
+                for target in grid.find_attackable_with(unit, weapon, to, to != from) {
+                    candidates.push(Action::Attack {
+                        from: from,
+                        to: to,
+                        facing: facing,
+                        target: target,
+                        weapon: weapon,
+                    });
+                }
+            }
+            let tile = grid.tile(to);
+            if tile.terrain.capture != 0 && tile.faction != Some(faction) {
+                candidates.push(Action::Capture {
+                    from: from,
+                    to: to,
+                    facing: facing,
+                });
+            }
+
+            for action in candidates {
+                if let Some(score) = score(&action) {
+                    if best.as_ref().map_or(true, |&(s, _)| score > s) {
+                        best = Some((score, action));
+                    }
+                }
+            }
+        }
+    }
+    match best {
+        // Waiting in place would only waste an action.
+        Some((_, Action::Wait { from, to, .. })) if from == to => None,
+        best => best.map(|(_, action)| action),
+    }
+}
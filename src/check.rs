@@ -0,0 +1,356 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::Path;
+
+use common::IntegerExt;
+use edge::EdgeKind;
+use faction::{Faction, FACTIONS};
+use info::{GameInfo, Terrain, UnitKind};
+use level::{faction_from_code, Level, Point};
+use load::{load_json, load_toml};
+
+const HQ_TERRAIN: &'static str = "hq";
+
+struct PlacedUnit {
+    kind: UnitKind,
+    faction: Option<Faction>,
+}
+
+#[inline]
+fn can_enter(kind: &UnitKind, terrain: &Terrain) -> bool {
+    kind.movement.class.costs.get(&terrain.name).map_or(false, |&c| c <= kind.movement.movement)
+}
+
+#[inline]
+fn sorted_layer(level: &Level, name: &str) -> Vec<(String, BTreeSet<Point>)> {
+    let mut layer = level.layers
+        .get(name)
+        .map(|l| l.iter().map(|(k, v)| (k.clone(), v.clone())).collect::<Vec<_>>())
+        .unwrap_or_else(Vec::new);
+    layer.sort_by(|a, b| a.0.cmp(&b.0));
+    layer
+}
+
+/// Finds everything in the level that would make the game panic or
+/// be unplayable, described in level coordinates.
+pub fn check_level(level: &Level, info: &GameInfo) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let mut layer_names = level.layers.keys().collect::<Vec<_>>();
+    layer_names.sort();
+    for name in layer_names {
+        if !["terrain", "units", "elevation", "teleporters", "edges"].contains(&&name[..]) {
+            problems.push(format!("unrecognized layer {:?}", name));
+        }
+    }
+    if !level.layers.contains_key("units") {
+        problems.push("the level has no 'units' layer".to_owned());
+    }
+    let default = info.terrain.get("default");
+    if default.is_none() {
+        problems.push("the info file has no 'default' terrain".to_owned());
+    }
+    let ((min_x, min_y), (w, h)) = match level.bounds() {
+        Some(bounds) => bounds,
+        None => {
+            problems.push("the level is empty".to_owned());
+            return problems;
+        }
+    };
+
+    // Leads positions past the edges of a wrapping map around to the other side.
+    let wrapped = |(x, y): (i32, i32)| {
+        let x = if level.wrap.horizontal() {
+            (x - min_x).mod_floor(w as i32) + min_x
+        } else {
+            x
+        };
+        let y = if level.wrap.vertical() {
+            (y - min_y).mod_floor(h as i32) + min_y
+        } else {
+            y
+        };
+        (x, y)
+    };
+
+    // Terrain.
+
+    let mut terrain = HashMap::new();
+    let mut owners = HashMap::new();
+    for (name, positions) in sorted_layer(level, "terrain") {
+        let info = match info.terrain.get(&name) {
+            Some(info) => info,
+            None => {
+                problems.push(format!("unrecognized terrain {:?} at {} tiles",
+                                      name,
+                                      positions.len()));
+                continue;
+            }
+        };
+        for Point(x, y, code) in positions {
+            match faction_from_code(code) {
+                Err(err) => problems.push(format!("tile at {:?}: {}", (x, y), err)),
+                Ok(Some(faction)) => {
+                    if info.capture == 0 {
+                        problems.push(format!("tile at {:?} is owned by {:?}, but {:?} cannot \
+                                               be captured",
+                                              (x, y),
+                                              faction,
+                                              name));
+                    }
+                    owners.insert((x, y), faction);
+                }
+                Ok(None) => {}
+            }
+            if let Some(prev) = terrain.insert((x, y), info.clone()) {
+                problems.push(format!("tile at {:?} is both {:?} and {:?}",
+                                      (x, y),
+                                      prev.name,
+                                      name));
+            }
+        }
+    }
+
+    let terrain_at = |pos: (i32, i32)| terrain.get(&pos).or(default);
+
+    // Elevation.
+
+    let mut heights = HashMap::new();
+    for (_, positions) in sorted_layer(level, "elevation") {
+        for Point(x, y, height) in positions {
+            if heights.insert((x, y), height).is_some() {
+                problems.push(format!("tile at {:?} has more than one elevation", (x, y)));
+            }
+        }
+    }
+
+    // Teleporters.
+
+    let mut teleporters = HashMap::new();
+    for (name, positions) in sorted_layer(level, "teleporters") {
+        if positions.len() != 2 {
+            problems.push(format!("teleporter {:?} has {} ends instead of 2",
+                                  name,
+                                  positions.len()));
+            continue;
+        }
+        let ends = positions.iter().map(|&Point(x, y, _)| (x, y)).collect::<Vec<_>>();
+        for (i, &end) in ends.iter().enumerate() {
+            if teleporters.insert(end, ends[1 - i]).is_some() {
+                problems.push(format!("tile at {:?} has more than one teleporter", end));
+            }
+        }
+    }
+
+    // Edges.
+
+    let topology = level.topology;
+
+    // Moves from one tile to the next that edges forbid.
+    let mut blocked = HashSet::new();
+    for (name, positions) in sorted_layer(level, "edges") {
+        let kind = match EdgeKind::from_name(&name) {
+            Some(kind) => kind,
+            None => {
+                problems.push(format!("unrecognized edge kind {:?} at {} tiles",
+                                      name,
+                                      positions.len()));
+                continue;
+            }
+        };
+        for Point(x, y, mask) in positions {
+            if mask == 0 || mask >= 1 << topology.direction_count() {
+                problems.push(format!("{} at {:?} has invalid sides {}", name, (x, y), mask));
+            }
+            for dir in 0..topology.direction_count() {
+                if mask & (1 << dir) == 0 {
+                    continue;
+                }
+                let npos = wrapped(topology.neighbor((x, y), dir));
+                if !kind.allows_movement(true) {
+                    blocked.insert(((x, y), npos));
+                }
+                if !kind.allows_movement(false) {
+                    blocked.insert((npos, (x, y)));
+                }
+            }
+        }
+    }
+
+    // Units.
+
+    let mut units = BTreeMap::new();
+    for (name, positions) in sorted_layer(level, "units") {
+        let kind = match info.unit_kinds.get(&name) {
+            Some(kind) => kind,
+            None => {
+                problems.push(format!("unrecognized unit kind {:?} at {} tiles",
+                                      name,
+                                      positions.len()));
+                continue;
+            }
+        };
+        for Point(x, y, code) in positions {
+            let faction = match faction_from_code(code) {
+                Ok(Some(faction)) => Some(faction),
+                Ok(None) => {
+                    problems.push(format!("{} at {:?} does not belong to a faction",
+                                          name,
+                                          (x, y)));
+                    None
+                }
+                Err(err) => {
+                    problems.push(format!("{} at {:?}: {}", name, (x, y), err));
+                    None
+                }
+            };
+            if let Some(terrain) = terrain_at((x, y)) {
+                if !can_enter(kind, terrain) {
+                    problems.push(format!("{} at {:?} stands on {:?}, which movement class {:?} \
+                                           cannot enter",
+                                          name,
+                                          (x, y),
+                                          terrain.name,
+                                          kind.movement.class.name));
+                }
+            }
+            let unit = PlacedUnit {
+                kind: kind.clone(),
+                faction: faction,
+            };
+            if let Some(prev) = units.insert((x, y), unit) {
+                problems.push(format!("{} and {} overlap at {:?}", prev.kind.name, name, (x, y)));
+            }
+        }
+    }
+
+    // Factions.
+
+    for &faction in FACTIONS {
+        if !units.values().any(|u| u.faction == Some(faction)) {
+            problems.push(format!("faction {:?} has no units", faction));
+        }
+        let has_hq = owners.iter()
+            .any(|(pos, &owner)| {
+                owner == faction && terrain_at(*pos).map_or(false, |t| t.name == HQ_TERRAIN)
+            });
+        if !has_hq {
+            problems.push(format!("faction {:?} has no HQ", faction));
+        }
+    }
+
+    // Regions that no unit will ever be able to reach.
+
+    let in_bounds = |(x, y): (i32, i32)| {
+        min_x <= x && x < min_x + w as i32 && min_y <= y && y < min_y + h as i32
+    };
+    let open = |from: (i32, i32), to: (i32, i32)| !blocked.contains(&(from, to));
+    let neighbors = |(x, y): (i32, i32)| {
+        let mut neighbors = (0..topology.direction_count())
+            .map(|dir| wrapped(topology.neighbor((x, y), dir)))
+            .filter(|&npos| in_bounds(npos) && open((x, y), npos))
+            .chain(teleporters.get(&(x, y)).cloned())
+            .collect::<Vec<_>>();
+        if level.diagonal_cost.is_some() {
+            for &(dx, dy) in &[(1, 1), (-1, 1), (-1, -1), (1, -1)] {
+                let a = wrapped((x + dx, y));
+                let b = wrapped((x, y + dy));
+                let npos = wrapped((x + dx, y + dy));
+                let around = (open((x, y), a) && open(a, npos)) ||
+                             (open((x, y), b) && open(b, npos));
+                if in_bounds(npos) && around {
+                    neighbors.push(npos);
+                }
+            }
+        }
+        neighbors
+    };
+
+    let mut reachable = BTreeSet::new();
+    for (&pos, unit) in &units {
+        let mut to_be_searched = vec![pos];
+        let mut seen = BTreeSet::new();
+        seen.insert(pos);
+        while let Some(pos) = to_be_searched.pop() {
+            reachable.insert(pos);
+            for npos in neighbors(pos) {
+                let enterable = terrain_at(npos).map_or(false, |t| can_enter(&unit.kind, t));
+                if enterable && seen.insert(npos) {
+                    to_be_searched.push(npos);
+                }
+            }
+        }
+    }
+
+    let passable = |pos: (i32, i32)| {
+        terrain_at(pos).map_or(false, |t| units.values().any(|u| can_enter(&u.kind, t)))
+    };
+    let mut seen = BTreeSet::new();
+    for y in min_y..min_y + h as i32 {
+        for x in min_x..min_x + w as i32 {
+            let pos = (x, y);
+            if reachable.contains(&pos) || !passable(pos) || !seen.insert(pos) {
+                continue;
+            }
+            let mut to_be_searched = vec![pos];
+            let mut size = 0;
+            while let Some(pos) = to_be_searched.pop() {
+                size += 1;
+                for npos in neighbors(pos) {
+                    if !reachable.contains(&npos) && passable(npos) && seen.insert(npos) {
+                        to_be_searched.push(npos);
+                    }
+                }
+            }
+            problems.push(format!("region of {} tiles at {:?} cannot be reached by any unit",
+                                  size,
+                                  pos));
+        }
+    }
+
+    problems
+}
+
+/// Runs `protoboard check`, and returns the exit code.
+pub fn run(level_path: &Path, info_path: &Path) -> i32 {
+    let level_name = level_path.display();
+    let info_name = info_path.display();
+
+    let mut problems = Vec::new();
+    let info = match load_toml(info_path, |m| problems.push(format!("{}: {}", info_name, m))) {
+        Ok(spec) => GameInfo::from_spec(spec),
+        Err(err) => Err(format!("{}", err)),
+    };
+    let info = match info {
+        Ok(info) => info,
+        Err(err) => {
+            writeln!(io::stderr(), "{}: {}", info_name, err).unwrap();
+            return 1;
+        }
+    };
+    let level = match load_json(level_path) {
+        Ok(spec) => Level::from_spec(spec),
+        Err(err) => Err(format!("{}", err)),
+    };
+    let level = match level {
+        Ok(level) => level,
+        Err(err) => {
+            writeln!(io::stderr(), "{}: {}", level_name, err).unwrap();
+            return 1;
+        }
+    };
+
+    for problem in check_level(&level, &info) {
+        problems.push(format!("{}: {}", level_name, problem));
+    }
+    for problem in &problems {
+        writeln!(io::stderr(), "{}", problem).unwrap();
+    }
+    if problems.is_empty() {
+        println!("{}: no problems found", level_name);
+        0
+    } else {
+        writeln!(io::stderr(), "{} problems found", problems.len()).unwrap();
+        1
+    }
+}
@@ -0,0 +1,173 @@
+use std::path::PathBuf;
+
+use faction::Faction;
+
+pub const USAGE: &'static str = "\
+usage: protoboard [play] [options]
+       protoboard edit [options]
+       protoboard check [<level>] [options]
+
+options:
+    --level <file>      the level to play, edit or check (default: level.json)
+    --info <file>       the info file (default: info.toml)
+    --window <w>x<h>    the window size (default: 1008x720)
+    --actions <n>       the number of actions per turn (default: 4)
+    --players <list>    the factions in turn order and who controls them
+                        (default: Red=human,Blue=human)
+    --save <file>       resume the game from <file> instead of the level if
+                        it exists, and save to it with F5
+                        (default: savegame.json, not resumed)
+    --turn-dir <dir>    play by email, exchanging turn files through <dir>
+    --play-as <faction> the faction played on this machine, when playing by
+                        email
+    --seed <n>          where the dice for luck start when starting a level
+                        (default: the level's seed, or a random one)";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Play,
+    Edit,
+    Check,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Player {
+    Human,
+    Ai,
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub command: Command,
+    pub level_path: PathBuf,
+    /// Whether the level was given with `--level` rather than defaulted.
+    pub level_given: bool,
+    pub info_path: PathBuf,
+    pub window_size: (u32, u32),
+    pub actions_per_turn: u32,
+    pub players: Vec<(Faction, Player)>,
+    pub save_path: Option<PathBuf>,
+    pub turn_dir: Option<PathBuf>,
+    /// The faction played on this machine, when playing by email.
+    pub play_as: Option<Faction>,
+    pub seed: Option<u32>,
+}
+
+fn parse_window_size(arg: &str) -> Result<(u32, u32), String> {
+    let mut parts = arg.splitn(2, 'x');
+    let w = parts.next().map(|s| s.parse::<u32>());
+    let h = parts.next().map(|s| s.parse::<u32>());
+    match (w, h) {
+        (Some(Ok(w)), Some(Ok(h))) if w > 0 && h > 0 => Ok((w, h)),
+        _ => Err(format!("invalid window size {:?}", arg)),
+    }
+}
+
+fn parse_players(arg: &str) -> Result<Vec<(Faction, Player)>, String> {
+    let mut players = Vec::new();
+    for part in arg.split(',') {
+        let mut parts = part.splitn(2, '=');
+        let name = parts.next().unwrap_or("");
+        let faction = Faction::from_name(name)
+            .ok_or_else(|| format!("unrecognized faction {:?}", name))?;
+        let player = match parts.next() {
+            Some("human") => Player::Human,
+            Some("ai") => Player::Ai,
+            _ => return Err(format!("expected {}=human or {}=ai", name, name)),
+        };
+        if players.iter().any(|&(f, _)| f == faction) {
+            return Err(format!("faction {:?} is listed twice", faction));
+        }
+        players.push((faction, player));
+    }
+    Ok(players)
+}
+
+impl Options {
+    pub fn parse<I>(mut args: I) -> Result<Options, String>
+        where I: Iterator<Item = String>
+    {
+        let mut options = Options {
+            command: Command::Play,
+            level_path: PathBuf::from("level.json"),
+            level_given: false,
+            info_path: PathBuf::from("info.toml"),
+            window_size: (1008, 720),
+            actions_per_turn: 4,
+            players: vec![(Faction::Red, Player::Human), (Faction::Blue, Player::Human)],
+            save_path: None,
+            turn_dir: None,
+            play_as: None,
+            seed: None,
+        };
+
+        let mut first = true;
+        while let Some(arg) = args.next() {
+            if first {
+                first = false;
+                let command = match &arg[..] {
+                    "play" => Some(Command::Play),
+                    "edit" => Some(Command::Edit),
+                    "check" => Some(Command::Check),
+                    _ => None,
+                };
+                if let Some(command) = command {
+                    options.command = command;
+                    continue;
+                }
+            }
+            if !arg.starts_with("--") {
+                if options.command == Command::Check {
+                    options.level_path = PathBuf::from(arg);
+                    options.level_given = true;
+                    continue;
+                }
+                return Err(format!("unexpected argument {:?}", arg));
+            }
+
+            let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+            match &arg[..] {
+                "--level" => {
+                    options.level_path = PathBuf::from(value);
+                    options.level_given = true;
+                }
+                "--info" => options.info_path = PathBuf::from(value),
+                "--window" => options.window_size = parse_window_size(&value)?,
+                "--actions" => {
+                    options.actions_per_turn = match value.parse::<u32>() {
+                        Ok(n) if n > 0 => n,
+                        _ => return Err(format!("invalid number of actions {:?}", value)),
+                    };
+                }
+                "--players" => options.players = parse_players(&value)?,
+                "--save" => options.save_path = Some(PathBuf::from(value)),
+                "--turn-dir" => options.turn_dir = Some(PathBuf::from(value)),
+                "--play-as" => {
+                    let faction = Faction::from_name(&value)
+                        .ok_or_else(|| format!("unrecognized faction {:?}", value))?;
+                    options.play_as = Some(faction);
+                }
+                "--seed" => {
+                    let seed = value.parse::<u32>()
+                        .map_err(|_| format!("invalid seed {:?}", value))?;
+                    options.seed = Some(seed);
+                }
+                _ => return Err(format!("unrecognized option {:?}", arg)),
+            }
+        }
+
+        // Whose turn it is says nothing about who is at this machine, so
+        // playing by email needs to be told.
+        match (options.turn_dir.is_some(), options.play_as) {
+            (true, None) => return Err("--turn-dir needs --play-as".to_owned()),
+            (false, Some(_)) => return Err("--play-as only applies with --turn-dir".to_owned()),
+            (true, Some(faction)) => {
+                if !options.players.iter().any(|&(f, _)| f == faction) {
+                    return Err(format!("faction {:?} is not playing", faction));
+                }
+            }
+            (false, None) => {}
+        }
+        Ok(options)
+    }
+}
@@ -1,5 +1,7 @@
 use std::cell::RefCell;
+use std::cmp;
 use std::fmt::Debug;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::time::{Duration, Instant};
 
@@ -8,6 +10,7 @@ use lru_time_cache::LruCache;
 use sdl2::rect::Rect;
 use sdl2_ttf::Font;
 
+use action::Action;
 use faction::Faction;
 use grid::Grid;
 use info::SpriteInfo;
@@ -18,6 +21,7 @@ const SCROLL_TIMEOUT_MS: u64 = 100;
 
 pub trait IntegerExt {
     fn div_floor(self, other: Self) -> Self;
+    fn mod_floor(self, other: Self) -> Self;
 }
 
 impl IntegerExt for i32 {
@@ -28,6 +32,17 @@ impl IntegerExt for i32 {
             (d, _) => d,
         }
     }
+
+    #[inline]
+    fn mod_floor(self, other: i32) -> i32 {
+        self - self.div_floor(other) * other
+    }
+}
+
+/// Returns the coordinate that repeats `x` every `period` closest to `center`.
+#[inline]
+fn nearest_copy(x: i32, center: i32, period: i32) -> i32 {
+    (x - center + period / 2).mod_floor(period) + center - period / 2
 }
 
 pub trait DurationExt {
@@ -58,6 +73,9 @@ pub enum Message {
     CancelReleased,
 
     FinishTurn,
+    SaveGame,
+    GoTo,
+    ToggleThreats,
 
     LeftClickAt(i32, i32),
     LeftReleasedAt(i32, i32),
@@ -68,17 +86,29 @@ pub enum Message {
 
     UnitSpent((u32, u32)),
     UnitMoved((u32, u32), (u32, u32)),
-    TargetConfirmed((u32, u32), (u32, u32)),
+    /// A unit took a step along the route to its go-to order.
+    OrderFollowed((u32, u32), (u32, u32)),
+    TargetConfirmed((u32, u32), (u32, u32), (u32, u32), usize),
 
     ApplyOneModal,
 
-    AttackSelected((u32, u32), (u32, u32)),
-    CaptureSelected((u32, u32)),
-    WaitSelected,
+    AttackSelected((u32, u32), (u32, u32), usize),
+    CaptureSelected((u32, u32), (u32, u32)),
+    WaitSelected((u32, u32), (u32, u32)),
+    TurnSelected((u32, u32), (u32, u32)),
     CancelSelected((u32, u32), (u32, u32)),
 
+    FacingSelected((u32, u32), (u32, u32)),
+
     TargetSelectorCanceled((u32, u32), (u32, u32)),
 
+    // Editor
+    NextBrush,
+    PrevBrush,
+    NextBrushFaction,
+    ResizeMap(i32, i32),
+    SaveLevel,
+
     Exit,
 }
 
@@ -124,6 +154,8 @@ pub struct State<'a> {
 
     pub turn_info: TurnInfo,
     pub grid: Grid,
+    /// The actions performed so far this turn.
+    pub actions: Vec<Action>,
 
     window_size: (u32, u32),
     pub tile_size: (u32, u32),
@@ -145,8 +177,7 @@ impl<'a> State<'a> {
     pub fn new(resources: ResourceManager<'a, 'static>,
                grid: Grid,
                tile_size: (u32, u32),
-               factions: Vec<Faction>,
-               actions_left: u32,
+               turn_info: TurnInfo,
                health_label_font: &'a Font,
                config: Config)
                -> State<'a> {
@@ -160,13 +191,9 @@ impl<'a> State<'a> {
         State {
             config: config,
             resources: resources,
-            turn_info: TurnInfo {
-                factions: factions,
-                current: 0,
-                max_actions_left: actions_left,
-                actions_left: actions_left,
-            },
+            turn_info: turn_info,
             grid: grid,
+            actions: Vec::new(),
             window_size: window_size,
             tile_size: tile_size,
             active_unit: None,
@@ -224,9 +251,18 @@ impl<'a> State<'a> {
 
     pub fn window_to_grid(&self, x: i32, y: i32) -> Option<(u32, u32)> {
         let y = (self.window_size.1 - 1) as i32 - y;
-        let (x, y) = self.viewport.from_view((x, y));
+        let (_, row) = self.viewport.from_view((x, y));
+
+        // Undo the topology's shift of the row to find the column.
+        let level_row = row + self.grid.offset().1;
+        let (sx, sy) = self.grid.topology().project((0, level_row), self.tile_size);
+        let (x, y) = self.viewport.from_view((x - sx, y - sy));
 
+        // Wrapping maps repeat forever.
         let (w, h) = self.grid.size();
+        let wrap = self.grid.wrap();
+        let x = if wrap.horizontal() { x.mod_floor(w as i32) } else { x };
+        let y = if wrap.vertical() { y.mod_floor(h as i32) } else { y };
         if 0 <= x && x < w as i32 && 0 <= y && y < h as i32 {
             Some((x as u32, y as u32))
         } else {
@@ -234,13 +270,52 @@ impl<'a> State<'a> {
         }
     }
 
+    /// Returns where on the screen `pos` is drawn, in grid coordinates.
+    ///
+    /// On wrapping maps, this is the copy of the tile among the columns
+    /// and rows starting at the bottom left of the window.
+    fn visible_copy(&self, pos: (u32, u32)) -> (i32, i32) {
+        let (w, h) = self.grid.size();
+        let wrap = self.grid.wrap();
+        let (left, bottom) = self.viewport.from_view((0, 0));
+        let (x, y) = (pos.0 as i32, pos.1 as i32);
+        let x = if wrap.horizontal() {
+            (x - left).mod_floor(w as i32) + left
+        } else {
+            x
+        };
+        let y = if wrap.vertical() {
+            (y - bottom).mod_floor(h as i32) + bottom
+        } else {
+            y
+        };
+        (x, y)
+    }
+
     pub fn translate_camera(&mut self, offset: (i32, i32)) {
         self.viewport.grid_offset.0 += offset.0;
         self.viewport.grid_offset.1 += offset.1;
     }
 
     pub fn tile_rect(&self, pos: (u32, u32)) -> Rect {
-        let (x, y) = self.viewport.to_view((pos.0 as i32, pos.1 as i32));
+        let copy = self.visible_copy(pos);
+        self.rect_at(copy, pos)
+    }
+
+    /// Returns the rectangle of `pos` drawn next to `from`, which differs
+    /// from `tile_rect` when the two are on opposite edges of a wrapping map.
+    pub fn tile_rect_from(&self, from: (u32, u32), pos: (u32, u32)) -> Rect {
+        let (fx, fy) = self.visible_copy(from);
+        let origin = self.grid.to_level(from);
+        let target = self.grid.to_level_near(from, pos);
+        self.rect_at((fx + target.0 - origin.0, fy + target.1 - origin.1), pos)
+    }
+
+    /// Returns the rectangle of the tile `pos`, drawn at `copy`.
+    fn rect_at(&self, copy: (i32, i32), pos: (u32, u32)) -> Rect {
+        let (x, y) = self.viewport.to_view(copy);
+        let (sx, sy) = self.grid.topology().project(self.grid.to_level(pos), self.tile_size);
+        let (x, y) = (x + sx, y + sy);
         let y = (self.window_size.1 - 1) as i32 - y;
 
         let (tw, th) = self.tile_size;
@@ -257,7 +332,17 @@ impl<'a> State<'a> {
         let min_y = self.viewport.grid_offset.1;
         let max_y = min_y + window_h as i32 - 1;
 
-        let (x, y) = (pos.0 as i32, pos.1 as i32);
+        let (mut x, mut y) = (pos.0 as i32, pos.1 as i32);
+
+        // Scroll towards the nearest copy of a tile on wrapping maps.
+        let (w, h) = self.grid.size();
+        let wrap = self.grid.wrap();
+        if wrap.horizontal() {
+            x = nearest_copy(x, (min_x + max_x) / 2, w as i32);
+        }
+        if wrap.vertical() {
+            y = nearest_copy(y, (min_y + max_y) / 2, h as i32);
+        }
 
         if x < min_x {
             self.viewport.grid_offset.0 += x - min_x;
@@ -350,15 +435,53 @@ impl<'a> State<'a> {
 pub struct TurnInfo {
     factions: Vec<Faction>,
     current: usize,
+    turn: u32,
     actions_left: u32,
     pub max_actions_left: u32,
 }
 
 impl TurnInfo {
+    #[inline]
+    pub fn new(factions: Vec<Faction>, max_actions_left: u32) -> TurnInfo {
+        TurnInfo {
+            factions: factions,
+            current: 0,
+            turn: 0,
+            actions_left: max_actions_left,
+            max_actions_left: max_actions_left,
+        }
+    }
+
+    /// Continues a game in the middle of the given turn.
+    pub fn resume(factions: Vec<Faction>,
+                  current: Faction,
+                  turn: u32,
+                  actions_left: u32,
+                  max_actions_left: u32)
+                  -> Result<TurnInfo, String> {
+        let current = factions.iter()
+            .position(|&f| f == current)
+            .ok_or_else(|| format!("faction {:?} is not playing", current))?;
+        Ok(TurnInfo {
+            factions: factions,
+            current: current,
+            turn: turn,
+            actions_left: cmp::min(actions_left, max_actions_left),
+            max_actions_left: max_actions_left,
+        })
+    }
+
     #[inline]
     pub fn end_turn(&mut self) {
         self.actions_left = self.max_actions_left;
         self.current = (self.current + 1) % self.factions.len();
+        self.turn += 1;
+    }
+
+    /// Returns the number of turns ended so far.
+    #[inline]
+    pub fn turn(&self) -> u32 {
+        self.turn
     }
 
     #[inline]
@@ -387,6 +510,20 @@ impl TurnInfo {
         }
     }
 
+    /// Removes every faction without any units left, and returns them.
+    pub fn remove_defeated(&mut self, grid: &Grid) -> Vec<Faction> {
+        let mut defeated = Vec::new();
+        for &faction in &self.factions {
+            if grid.units().all(|u| u.faction != faction) {
+                defeated.push(faction);
+            }
+        }
+        for &faction in &defeated {
+            self.remove_faction(faction);
+        }
+        defeated
+    }
+
     #[inline]
     pub fn can_act(&self, unit: &Unit) -> bool {
         unit.faction == self.current_faction() && self.actions_left > 0 && !unit.spent
@@ -398,7 +535,24 @@ impl TurnInfo {
     }
 }
 
-pub struct Config {}
+pub struct Config {
+    /// The name and schema of the level, for savegames.
+    pub level_name: String,
+    pub level_schema: String,
+    /// The file to save the game to.
+    pub save_path: PathBuf,
+    /// The factions controlled by the computer.
+    pub ai_factions: Vec<Faction>,
+    /// The directory to exchange turn files through, when playing by email.
+    pub turn_dir: Option<PathBuf>,
+}
+
+impl Config {
+    #[inline]
+    pub fn is_ai(&self, faction: Faction) -> bool {
+        self.ai_factions.contains(&faction)
+    }
+}
 
 pub trait BehaviorDebug<S>: Behavior<S> + Debug {}
 
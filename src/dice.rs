@@ -0,0 +1,35 @@
+/// The random numbers behind luck in combat.
+///
+/// This is a plain xorshift generator rather than anything from `rand`,
+/// so that its whole state is a single number that can be saved, and so
+/// that every machine replaying a game rolls exactly the same.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dice {
+    state: u32,
+}
+
+impl Dice {
+    /// Creates dice that roll the same sequence for the same seed.
+    #[inline]
+    pub fn new(seed: u32) -> Dice {
+        // Xorshift never leaves zero.
+        Dice { state: if seed == 0 { 0x9e37_79b9 } else { seed } }
+    }
+
+    /// Returns the current state, which `new` takes back to carry on
+    /// where these dice left off.
+    #[inline]
+    pub fn state(&self) -> u32 {
+        self.state
+    }
+
+    /// Returns a number from `0` up to but not including `sides`.
+    pub fn roll(&mut self, sides: u32) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x % sides
+    }
+}
@@ -0,0 +1,59 @@
+/// Something on the border between two tiles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// Blocks movement, attacks and line of sight.
+    Wall,
+    /// Blocks movement, but can be attacked across.
+    River,
+    /// Blocks movement and melee attacks, but not line of sight.
+    Cliff,
+    /// Can be jumped down from the tile it belongs to, but not climbed up.
+    Ledge,
+}
+
+impl EdgeKind {
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match *self {
+            EdgeKind::Wall => "wall",
+            EdgeKind::River => "river",
+            EdgeKind::Cliff => "cliff",
+            EdgeKind::Ledge => "ledge",
+        }
+    }
+
+    #[inline]
+    pub fn from_name(name: &str) -> Option<EdgeKind> {
+        match name {
+            "wall" => Some(EdgeKind::Wall),
+            "river" => Some(EdgeKind::River),
+            "cliff" => Some(EdgeKind::Cliff),
+            "ledge" => Some(EdgeKind::Ledge),
+            _ => None,
+        }
+    }
+
+    /// Whether units can move across the edge, from the tile it belongs to
+    /// (`outwards`) or into it.
+    #[inline]
+    pub fn allows_movement(&self, outwards: bool) -> bool {
+        match *self {
+            EdgeKind::Wall | EdgeKind::River | EdgeKind::Cliff => false,
+            EdgeKind::Ledge => outwards,
+        }
+    }
+
+    /// Whether melee and spear attacks can be made across the edge.
+    #[inline]
+    pub fn allows_melee(&self) -> bool {
+        match *self {
+            EdgeKind::Wall | EdgeKind::Cliff => false,
+            EdgeKind::River | EdgeKind::Ledge => true,
+        }
+    }
+
+    #[inline]
+    pub fn allows_sight(&self) -> bool {
+        *self != EdgeKind::Wall
+    }
+}
@@ -0,0 +1,250 @@
+use std::cmp;
+use std::path::PathBuf;
+
+use glorious::{Behavior, Color, Label, Renderer};
+use sdl2::rect::Rect;
+use sdl2_ttf::Font;
+
+use common::{Message, State};
+use faction::Faction;
+use grid_manager::GridManager;
+use info::{GameInfo, Terrain, UnitKind};
+use level::Level;
+use load::save_json;
+use tile::Tile;
+use topology::Topology;
+use unit::Unit;
+
+const BG_COLOR: Color = Color(0x00, 0x00, 0x00, 0x77);
+const TEXT_COLOR: Color = Color(0xff, 0xff, 0xff, 0xff);
+const POS: (i32, i32) = (774, 50);
+const SIZE: (u32, u32) = (200, 50);
+
+const FACTIONS: [Option<Faction>; 3] = [None, Some(Faction::Red), Some(Faction::Blue)];
+
+#[derive(Clone, Debug)]
+enum Brush {
+    Terrain(Terrain),
+    Unit(UnitKind),
+}
+
+/// A scene for painting terrain and placing units on a level.
+#[derive(Debug)]
+pub struct Editor {
+    grid_manager: GridManager,
+    name: String,
+    schema: String,
+    /// The seed of the level, rather than the state of the dice.
+    seed: Option<u32>,
+    path: PathBuf,
+    default: Terrain,
+    brushes: Vec<Brush>,
+    brush: usize,
+    faction: usize,
+    painting: bool,
+    line_spacing: u32,
+    brush_labels: Vec<Label>,
+    faction_labels: Vec<Label>,
+}
+
+impl Editor {
+    pub fn new(font: &Font,
+               info: &GameInfo,
+               level: &Level,
+               path: PathBuf,
+               state: &State)
+               -> Editor {
+        let mut terrain = info.terrain.values().cloned().collect::<Vec<_>>();
+        terrain.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut unit_kinds = info.unit_kinds.values().cloned().collect::<Vec<_>>();
+        unit_kinds.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let brushes = terrain.into_iter()
+            .map(Brush::Terrain)
+            .chain(unit_kinds.into_iter().map(Brush::Unit))
+            .collect::<Vec<_>>();
+
+        let (_, scale_y) = state.resources.device().scale();
+        let line_spacing = font.recommended_line_spacing();
+        let line_spacing = (line_spacing as f32 / scale_y).round() as u32;
+        let brush_labels = brushes.iter()
+            .map(|brush| {
+                let text = match *brush {
+                    Brush::Terrain(ref terrain) => format!("Terrain: {}", terrain.name),
+                    Brush::Unit(ref kind) => format!("Unit: {}", kind.name),
+                };
+                Label::new(font, &text, TEXT_COLOR, state.resources.device())
+            })
+            .collect();
+        let faction_labels = FACTIONS.iter()
+            .map(|faction| {
+                let text = match *faction {
+                    Some(faction) => format!("Faction: {}", faction.name()),
+                    None => "Faction: none".to_owned(),
+                };
+                Label::new(font, &text, TEXT_COLOR, state.resources.device())
+            })
+            .collect();
+
+        let (w, h) = state.grid.size();
+        Editor {
+            grid_manager: GridManager::new((w / 2, h / 2)),
+            name: level.name.clone(),
+            schema: level.schema.clone(),
+            seed: level.seed,
+            path: path,
+            default: info.terrain["default"].clone(),
+            brushes: brushes,
+            brush: 0,
+            faction: 0,
+            painting: false,
+            line_spacing: line_spacing,
+            brush_labels: brush_labels,
+            faction_labels: faction_labels,
+        }
+    }
+
+    /// Paints the current brush at the cursor.
+    fn paint(&mut self, state: &mut State) {
+        let pos = self.grid_manager.cursor();
+        let faction = FACTIONS[self.faction];
+        match self.brushes[self.brush] {
+            Brush::Terrain(ref terrain) => {
+                let tile = state.grid.tile_mut(pos);
+                tile.terrain = terrain.clone();
+                tile.faction = if terrain.capture != 0 {
+                    faction
+                } else {
+                    None
+                };
+                tile.capture = None;
+            }
+            Brush::Unit(ref kind) => {
+                let faction = match faction {
+                    Some(faction) => faction,
+                    None => {
+                        warn!("Units must belong to a faction");
+                        return;
+                    }
+                };
+                if state.grid.unit(pos).is_some() {
+                    state.grid.remove_unit(pos);
+                }
+                state.grid.add_unit(Unit::new(kind.clone(), faction), pos);
+            }
+        }
+    }
+
+    /// Removes the unit at the cursor, or resets the terrain if there is none.
+    fn erase(&mut self, state: &mut State) {
+        let pos = self.grid_manager.cursor();
+        if state.grid.unit(pos).is_some() {
+            state.grid.remove_unit(pos);
+        } else {
+            let tile = state.grid.tile_mut(pos);
+            tile.terrain = self.default.clone();
+            tile.faction = None;
+            tile.capture = None;
+            tile.elevation = 0;
+        }
+    }
+
+    fn resize(&mut self, delta: (i32, i32), state: &mut State) {
+        let (w, h) = state.grid.size();
+        // Rows of a hex map that wraps vertically only line up across the
+        // edge if there is an even number of them.
+        let rows = match (state.grid.topology(), state.grid.wrap().vertical()) {
+            (Topology::Hex, true) => 2,
+            _ => 1,
+        };
+        let w = cmp::max(1, w as i32 + delta.0) as u32;
+        let h = cmp::max(rows, h as i32 + rows * delta.1) as u32;
+        let default = Tile {
+            terrain: self.default.clone(),
+            faction: None,
+            capture: None,
+            elevation: 0,
+        };
+        state.grid = state.grid.resized((w, h), &default);
+
+        let (x, y) = self.grid_manager.cursor();
+        self.grid_manager.move_cursor_to((cmp::min(x, w - 1), cmp::min(y, h - 1)), state);
+        info!("Resized map to {}x{}", w, h);
+    }
+
+    fn save(&self, state: &State) {
+        let mut level = Level::from_grid(&state.grid, self.name.clone(), self.schema.clone());
+        level.seed = self.seed;
+        match save_json(&self.path, &level.to_spec()) {
+            Ok(()) => info!("Saved level to {}", self.path.display()),
+            Err(err) => error!("Could not save level to {}: {}", self.path.display(), err),
+        }
+    }
+}
+
+impl<'a> Behavior<State<'a>> for Editor {
+    type Message = Message;
+
+    fn update(&mut self, state: &mut State<'a>, _queue: &mut Vec<Message>) {
+        self.grid_manager.update(state);
+    }
+
+    fn handle(&mut self, state: &mut State<'a>, message: Message, _queue: &mut Vec<Message>) {
+        use common::Message::*;
+
+        match message {
+            Confirm => self.paint(state),
+            Cancel => self.erase(state),
+
+            MoveCursorUp => self.grid_manager.move_cursor_relative((0, 1), state),
+            MoveCursorDown => self.grid_manager.move_cursor_relative((0, -1), state),
+            MoveCursorLeft => self.grid_manager.move_cursor_relative((-1, 0), state),
+            MoveCursorRight => self.grid_manager.move_cursor_relative((1, 0), state),
+
+            MoveCameraUp => state.translate_camera((0, 1)),
+            MoveCameraDown => state.translate_camera((0, -1)),
+            MoveCameraLeft => state.translate_camera((-1, 0)),
+            MoveCameraRight => state.translate_camera((1, 0)),
+
+            LeftClickAt(x, y) => {
+                if state.window_to_grid(x, y).is_some() {
+                    self.grid_manager.mouse_moved_to(x, y, state);
+                    self.painting = true;
+                    self.paint(state);
+                }
+            }
+            LeftReleasedAt(_, _) => self.painting = false,
+            RightClickAt(x, y) => {
+                if state.window_to_grid(x, y).is_some() {
+                    self.grid_manager.mouse_moved_to(x, y, state);
+                    self.erase(state);
+                }
+            }
+            MouseMovedTo(x, y) => {
+                let prev = self.grid_manager.cursor();
+                self.grid_manager.mouse_moved_to(x, y, state);
+                if self.painting && self.grid_manager.cursor() != prev {
+                    self.paint(state);
+                }
+            }
+
+            NextBrush => self.brush = (self.brush + 1) % self.brushes.len(),
+            PrevBrush => self.brush = (self.brush + self.brushes.len() - 1) % self.brushes.len(),
+            NextBrushFaction => self.faction = (self.faction + 1) % FACTIONS.len(),
+            ResizeMap(dx, dy) => self.resize((dx, dy), state),
+            SaveLevel => self.save(state),
+
+            _ => {}
+        }
+    }
+
+    fn render(&mut self, state: &State<'a>, renderer: &mut Renderer) {
+        self.grid_manager.render(state, renderer);
+
+        let (x, y) = POS;
+        renderer.set_draw_color(BG_COLOR);
+        renderer.fill_rect(Rect::new(x - 5, y, SIZE.0, SIZE.1)).unwrap();
+        self.brush_labels[self.brush].render(renderer, x, y);
+        self.faction_labels[self.faction].render(renderer, x, y + self.line_spacing as i32);
+    }
+}
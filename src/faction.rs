@@ -1,5 +1,26 @@
+pub const FACTIONS: &'static [Faction] = &[Faction::Red, Faction::Blue];
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Faction {
     Red,
     Blue,
 }
+
+impl Faction {
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Faction::Red => "Red",
+            Faction::Blue => "Blue",
+        }
+    }
+
+    #[inline]
+    pub fn from_name(name: &str) -> Option<Faction> {
+        match name {
+            "Red" => Some(Faction::Red),
+            "Blue" => Some(Faction::Blue),
+            _ => None,
+        }
+    }
+}
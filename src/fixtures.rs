@@ -0,0 +1,188 @@
+//! Game info and small levels for tests.
+
+use std::collections::{BTreeSet, HashMap};
+
+use spec::LevelSpec;
+
+use grid::Grid;
+use info::GameInfo;
+use level::Level;
+use load::parse_toml;
+
+/// The game info for tests. It starts out like `info.toml`, but is kept
+/// apart from it so that balancing the game does not break the tests, and
+/// adds a free `road` terrain.
+const INFO: &'static str = r#"
+defense_classes = ["light", "medium", "heavy"]
+
+[elevation]
+  range = 1
+  attack = 0.1
+  uphill = 0.1
+  climb = 1
+
+[combat]
+  formula = "standard"
+  ranged_retaliation = true
+  scale_retaliation = true
+  retaliation = 1.0
+  flanking = 0.1
+  pincer = 0.2
+  support = 0.05
+  side_penalty = 0.25
+  rear_penalty = 0.5
+
+[terrain.default]
+  defense = 0.0
+
+[terrain.grass]
+  defense = 0.05
+
+[terrain.road]
+  defense = 0.0
+
+[terrain.woods]
+  defense = 0.1
+
+[terrain.mountains]
+  defense = 0.3
+
+[terrain.city]
+  defense = 0.4
+  capture = 20
+
+[terrain.hq]
+  defense = 0.4
+  capture = 20
+
+[movement_classes.normal]
+  default = 1
+  city = 1
+  hq = 1
+  grass = 1
+  road = 0
+  woods = 2
+  mountains = 100000
+
+[movement_classes.off-road]
+  default = 1
+  city = 1
+  hq = 1
+  grass = 1
+  road = 0
+  woods = 1
+  mountains = 2
+
+[unit_kinds.warrior]
+  sprite = { texture = "" }
+  attack = { damage = 2.0, range = { kind = "melee" }, modifiers = { light = 3.0 } }
+  defense = { class = "medium", defense = 0.2 }
+  movement = { class = "normal", movement = 6 }
+  zone_of_control = { kind = "stop" }
+
+[unit_kinds.archer]
+  sprite = { texture = "" }
+  defense = { class = "light", defense = 0.2 }
+  movement = { class = "normal", movement = 4 }
+  zone_of_control = { kind = "stop" }
+  terrain = { mountains = { attack = 0.2 } }
+
+[[unit_kinds.archer.weapons]]
+  name = "bow"
+  damage = 4.0
+  range = { kind = "ranged", min = 2, max = 3 }
+  modifiers = { heavy = 1.2 }
+
+[[unit_kinds.archer.weapons]]
+  name = "dagger"
+  damage = 1.0
+  range = { kind = "melee" }
+
+[unit_kinds.defender]
+  sprite = { texture = "" }
+  attack = { damage = 2.0, range = { kind = "melee" } }
+  defense = { class = "heavy", defense = 0.5 }
+  movement = { class = "normal", movement = 4 }
+  zone_of_control = { kind = "stop" }
+
+[unit_kinds.raccoon]
+  sprite = { texture = "" }
+  first_strike = true
+  defense = { class = "medium", defense = 0.2 }
+  movement = { class = "off-road", movement = 3 }
+  zone_of_control = { kind = "ignore" }
+  capture = 10
+
+[unit_kinds.raccoon.attack]
+  damage = 4.0
+  range = { kind = "spear", range = 3, pierce = { enemies = 1, falloff = 0.5 } }
+
+[unit_kinds.catapult]
+  sprite = { texture = "" }
+  defense = { class = "heavy", defense = 0.1 }
+  terrain = { woods = { attack = -0.3, defense = -0.1 } }
+  movement = { class = "normal", movement = 3 }
+  zone_of_control = { kind = "cost", cost = 2 }
+
+[unit_kinds.catapult.attack]
+  damage = 3.0
+  range = { kind = "area", min = 3, max = 5, radius = 1, falloff = 0.5, friendly_fire = true }
+"#;
+
+/// Loads the game info for tests.
+pub fn info() -> GameInfo {
+    let spec = parse_toml(INFO, |m| panic!("{}", m)).expect("could not load the test info");
+    GameInfo::from_spec(spec).expect("could not validate the test info")
+}
+
+/// Creates a level with the given topology from a picture of its terrain
+/// and a list of units.
+///
+/// Each character in `rows` is a tile: `.` grass, `r` road, `w` woods, `m`
+/// mountains, `c` city, `h` hq, and anything else default terrain. The
+/// first row is `y = 0`. Units are given by their kind, position and
+/// faction code.
+pub fn level(topology: Option<&str>, rows: &[&str], units: &[(&str, (u32, u32), u32)]) -> Level {
+    let mut terrain = HashMap::new();
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            let name = match c {
+                '.' => "grass",
+                'r' => "road",
+                'w' => "woods",
+                'm' => "mountains",
+                'c' => "city",
+                'h' => "hq",
+                _ => "default",
+            };
+            terrain.entry(name.to_owned())
+                .or_insert_with(BTreeSet::new)
+                .insert((x as i32, y as i32, 0));
+        }
+    }
+    let mut kinds = HashMap::new();
+    for &(kind, (x, y), faction) in units {
+        kinds.entry(kind.to_owned())
+            .or_insert_with(BTreeSet::new)
+            .insert((x as i32, y as i32, faction));
+    }
+    let mut layers = HashMap::new();
+    layers.insert("terrain".to_owned(), terrain);
+    layers.insert("units".to_owned(), kinds);
+    Level::from_spec(LevelSpec {
+            name: "test".to_owned(),
+            schema: "protoboard".to_owned(),
+            topology: topology.map(|t| t.to_owned()),
+            diagonal_cost: None,
+            wrap: None,
+            luck: None,
+            seed: None,
+            layers: layers,
+        })
+        .expect("could not validate level")
+}
+
+/// Creates a square grid like `level` does.
+pub fn grid(rows: &[&str], units: &[(&str, (u32, u32), u32)]) -> Grid {
+    level(None, rows, units).create_grid(&info())
+}
@@ -1,18 +1,47 @@
-use std::collections::{btree_map, BTreeMap, BTreeSet};
+use std::cmp::{self, Ordering};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
 use std::mem;
 
-use rand::{thread_rng, Rng};
-
+use common::IntegerExt;
+use dice::Dice;
+use edge::EdgeKind;
 use range::AttackRange;
-use info::RangeKind;
+use info::{CombatInfo, ElevationInfo, RangeKind, ZoneOfControl};
 use unit::Unit;
 use tile::Tile;
+use topology::{Metric, Topology, Wrap};
+
+/// Pairs of square grid directions whose diagonal a unit can step along.
+const DIAGONALS: [(usize, usize); 4] = [(0, 1), (1, 2), (2, 3), (3, 0)];
+
+/// What an attack could do to its target, in health points.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Forecast {
+    pub min: u32,
+    pub max: u32,
+    /// The chance of destroying the target, in percent.
+    pub kill_chance: u32,
+}
 
 #[derive(Clone, Debug)]
 pub struct Grid {
     size: (u32, u32),
+    offset: (i32, i32),
     units: Box<[Option<Unit>]>,
     tiles: Box<[Tile]>,
+    /// Maps each teleporter to the tile it leads to, and the cost of using it.
+    teleporters: BTreeMap<(u32, u32), ((u32, u32), u32)>,
+    /// Walls and the like, by the tile and the direction of its side.
+    edges: BTreeMap<((u32, u32), usize), EdgeKind>,
+    topology: Topology,
+    /// The extra cost of a diagonal step, if they are allowed at all.
+    diagonal_cost: Option<u32>,
+    wrap: Wrap,
+    elevation: ElevationInfo,
+    combat: CombatInfo,
+    /// How much damage may randomly vary either way, in percent.
+    luck: u32,
+    dice: Dice,
 }
 
 impl Grid {
@@ -29,8 +58,18 @@ impl Grid {
             .collect::<Vec<_>>();
         Grid {
             size: size,
+            offset: (0, 0),
             units: vec![None; count].into_boxed_slice(),
             tiles: terrain.into_boxed_slice(),
+            teleporters: BTreeMap::new(),
+            edges: BTreeMap::new(),
+            topology: Topology::Square,
+            diagonal_cost: None,
+            wrap: Wrap::None,
+            elevation: ElevationInfo::default(),
+            combat: CombatInfo::default(),
+            luck: 0,
+            dice: Dice::new(0),
         }
     }
 
@@ -39,6 +78,471 @@ impl Grid {
         self.size
     }
 
+    /// Returns the level coordinates of the tile at (0, 0).
+    #[inline]
+    pub fn offset(&self) -> (i32, i32) {
+        self.offset
+    }
+
+    #[inline]
+    pub fn set_offset(&mut self, offset: (i32, i32)) {
+        self.offset = offset;
+    }
+
+    #[inline]
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    #[inline]
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = topology;
+    }
+
+    /// Returns how much more than its terrain a diagonal step costs, or
+    /// `None` if units can only move to neighboring tiles.
+    #[inline]
+    pub fn diagonal_cost(&self) -> Option<u32> {
+        self.diagonal_cost
+    }
+
+    /// Allows diagonal steps on square grids, for `cost` on top of the
+    /// terrain, or forbids them.
+    #[inline]
+    pub fn set_diagonal_cost(&mut self, cost: Option<u32>) {
+        self.diagonal_cost = cost;
+    }
+
+    #[inline]
+    pub fn wrap(&self) -> Wrap {
+        self.wrap
+    }
+
+    #[inline]
+    pub fn set_wrap(&mut self, wrap: Wrap) {
+        self.wrap = wrap;
+    }
+
+    /// Returns how differences in height affect units.
+    #[inline]
+    pub fn elevation_rules(&self) -> &ElevationInfo {
+        &self.elevation
+    }
+
+    #[inline]
+    pub fn set_elevation_rules(&mut self, rules: ElevationInfo) {
+        self.elevation = rules;
+    }
+
+    /// Sets how damage is worked out and when units strike back.
+    #[inline]
+    pub fn set_combat_rules(&mut self, rules: CombatInfo) {
+        self.combat = rules;
+    }
+
+    #[inline]
+    pub fn luck(&self) -> u32 {
+        self.luck
+    }
+
+    #[inline]
+    pub fn dice(&self) -> &Dice {
+        &self.dice
+    }
+
+    /// Lets damage vary by up to `luck` percent either way, as rolled by
+    /// `dice`.
+    #[inline]
+    pub fn set_luck(&mut self, luck: u32, dice: Dice) {
+        self.luck = luck;
+        self.dice = dice;
+    }
+
+    /// Returns how many levels higher `from` is than `to`; negative if it
+    /// is lower.
+    #[inline]
+    pub fn height_above(&self, from: (u32, u32), to: (u32, u32)) -> i32 {
+        self.tile(from).elevation as i32 - self.tile(to).elevation as i32
+    }
+
+    /// Returns the extra cost of climbing from one tile to the next.
+    #[inline]
+    fn climb_cost(&self, from: (u32, u32), to: (u32, u32)) -> u32 {
+        let up = cmp::max(0, self.height_above(to, from)) as u32;
+        up.saturating_mul(self.elevation.climb)
+    }
+
+    /// Converts grid coordinates to level coordinates, which the topology
+    /// works in.
+    #[inline]
+    pub fn to_level(&self, pos: (u32, u32)) -> (i32, i32) {
+        (pos.0 as i32 + self.offset.0, pos.1 as i32 + self.offset.1)
+    }
+
+    /// Converts level coordinates to grid coordinates, if they are on the grid.
+    ///
+    /// On wrapping maps, coordinates past an edge lead around to the other.
+    #[inline]
+    pub fn from_level(&self, pos: (i32, i32)) -> Option<(u32, u32)> {
+        let (w, h) = self.size;
+        let (mut x, mut y) = (pos.0 - self.offset.0, pos.1 - self.offset.1);
+        if self.wrap.horizontal() {
+            x = x.mod_floor(w as i32);
+        }
+        if self.wrap.vertical() {
+            y = y.mod_floor(h as i32);
+        }
+        if 0 <= x && x < w as i32 && 0 <= y && y < h as i32 {
+            Some((x as u32, y as u32))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the level coordinates of `to` as seen from `from`.
+    ///
+    /// On wrapping maps, every tile repeats beyond the edges, and this is
+    /// the nearest copy.
+    pub fn to_level_near(&self, from: (u32, u32), to: (u32, u32)) -> (i32, i32) {
+        let (w, h) = (self.size.0 as i32, self.size.1 as i32);
+        let origin = self.to_level(from);
+        let target = self.to_level(to);
+        let xs = if self.wrap.horizontal() { vec![-w, 0, w] } else { vec![0] };
+        let ys = if self.wrap.vertical() { vec![-h, 0, h] } else { vec![0] };
+        let mut best = target;
+        for &dy in &ys {
+            for &dx in &xs {
+                let copy = (target.0 + dx, target.1 + dy);
+                if self.topology.distance(origin, copy) < self.topology.distance(origin, best) {
+                    best = copy;
+                }
+            }
+        }
+        best
+    }
+
+    /// Returns the number of steps between two tiles.
+    #[inline]
+    pub fn distance(&self, a: (u32, u32), b: (u32, u32)) -> u32 {
+        self.topology.distance(self.to_level(a), self.to_level_near(a, b))
+    }
+
+    /// Returns the direction from one tile to an adjacent one.
+    #[inline]
+    pub fn direction(&self, from: (u32, u32), to: (u32, u32)) -> Option<usize> {
+        self.topology.direction(self.to_level(from), self.to_level_near(from, to))
+    }
+
+    /// Returns the direction of the first step on a straight line from
+    /// `from` towards `to`, or `None` if they are the same tile.
+    pub fn heading(&self, from: (u32, u32), to: (u32, u32)) -> Option<usize> {
+        let line = self.topology.line(self.to_level(from), self.to_level_near(from, to));
+        if line.len() < 2 {
+            None
+        } else {
+            self.topology.direction(line[0], line[1])
+        }
+    }
+
+    /// Whether a unit can step from one tile straight to the other, either
+    /// to a neighbor or diagonally, if that is allowed.
+    #[inline]
+    pub fn is_step(&self, from: (u32, u32), to: (u32, u32)) -> bool {
+        self.direction(from, to).is_some() || self.is_diagonal(from, to)
+    }
+
+    #[inline]
+    fn is_diagonal(&self, from: (u32, u32), to: (u32, u32)) -> bool {
+        let (origin, target) = (self.to_level(from), self.to_level_near(from, to));
+        let (dx, dy) = (target.0 - origin.0, target.1 - origin.1);
+        self.diagonal_cost.is_some() && self.topology == Topology::Square &&
+        dx.abs() == 1 && dy.abs() == 1
+    }
+
+    /// Returns a copy of the grid with a new size.
+    ///
+    /// New tiles are filled with `default`, and units outside the new size
+    /// are dropped.
+    pub fn resized(&self, size: (u32, u32), default: &Tile) -> Grid {
+        let (w, h) = self.size;
+        let mut grid = Grid::new(size, |(x, y)| {
+            if x < w && y < h {
+                self.tile((x, y)).clone()
+            } else {
+                default.clone()
+            }
+        });
+        grid.offset = self.offset;
+        grid.topology = self.topology;
+        grid.diagonal_cost = self.diagonal_cost;
+        grid.wrap = self.wrap;
+        grid.elevation = self.elevation.clone();
+        grid.combat = self.combat.clone();
+        grid.luck = self.luck;
+        grid.dice = self.dice;
+        for y in 0..cmp::min(h, size.1) {
+            for x in 0..cmp::min(w, size.0) {
+                if let Some(unit) = self.unit((x, y)) {
+                    grid.add_unit(unit.clone(), (x, y));
+                }
+            }
+        }
+        for (&from, &(to, cost)) in &self.teleporters {
+            if from.0 < size.0 && from.1 < size.1 && to.0 < size.0 && to.1 < size.1 {
+                grid.teleporters.insert(from, (to, cost));
+            }
+        }
+        for (&(pos, dir), &kind) in &self.edges {
+            if pos.0 < size.0 && pos.1 < size.1 {
+                grid.edges.insert((pos, dir), kind);
+            }
+        }
+        grid
+    }
+
+    /// Makes units on `from` able to step to `to` for `cost` movement.
+    #[inline]
+    pub fn set_teleporter(&mut self, from: (u32, u32), to: (u32, u32), cost: u32) {
+        self.teleporters.insert(from, (to, cost));
+    }
+
+    /// Returns where the teleporter at `pos` leads, and what it costs.
+    #[inline]
+    pub fn teleporter(&self, pos: (u32, u32)) -> Option<((u32, u32), u32)> {
+        self.teleporters.get(&pos).cloned()
+    }
+
+    /// Returns the tile next to `pos` in the direction `dir`, if there is one.
+    #[inline]
+    pub fn neighbor(&self, pos: (u32, u32), dir: usize) -> Option<(u32, u32)> {
+        self.from_level(self.topology.neighbor(self.to_level(pos), dir))
+    }
+
+    /// Puts an edge on the side of `pos` facing `dir`, or removes it.
+    pub fn set_edge(&mut self, pos: (u32, u32), dir: usize, kind: Option<EdgeKind>) {
+        match kind {
+            Some(kind) => self.edges.insert((pos, dir), kind),
+            None => self.edges.remove(&(pos, dir)),
+        };
+    }
+
+    /// Returns every edge, with the tile and direction of its side.
+    pub fn edges(&self) -> Vec<((u32, u32), usize, EdgeKind)> {
+        self.edges.iter().map(|(&(pos, dir), &kind)| (pos, dir, kind)).collect()
+    }
+
+    /// Checks the edges on both sides of the border between `pos` and its
+    /// neighbor in direction `dir`. `allows` is given each edge and whether
+    /// it belongs to `pos`.
+    fn across<F>(&self, pos: (u32, u32), dir: usize, allows: F) -> bool
+        where F: Fn(EdgeKind, bool) -> bool
+    {
+        let npos = match self.neighbor(pos, dir) {
+            Some(npos) => npos,
+            None => return false,
+        };
+        let outwards = self.edges.get(&(pos, dir)).map_or(true, |&e| allows(e, true));
+        let inwards = self.edges
+            .get(&(npos, self.topology.opposite(dir)))
+            .map_or(true, |&e| allows(e, false));
+        outwards && inwards
+    }
+
+    #[inline]
+    pub fn can_move_across(&self, pos: (u32, u32), dir: usize) -> bool {
+        self.across(pos, dir, |e, outwards| e.allows_movement(outwards))
+    }
+
+    #[inline]
+    pub fn can_melee_across(&self, pos: (u32, u32), dir: usize) -> bool {
+        self.across(pos, dir, |e, _| e.allows_melee())
+    }
+
+    #[inline]
+    fn can_see_across(&self, pos: (u32, u32), dir: usize) -> bool {
+        self.across(pos, dir, |e, _| e.allows_sight())
+    }
+
+    /// Whether no wall stands on the straight line between two tiles.
+    ///
+    /// On square grids, a line passing exactly through a corner is only
+    /// blocked if both ways around the corner are.
+    pub fn line_of_sight(&self, from: (u32, u32), to: (u32, u32)) -> bool {
+        if self.topology != Topology::Square {
+            let line = self.topology.line(self.to_level(from), self.to_level_near(from, to));
+            return line.windows(2).all(|pair| {
+                let dir = self.topology
+                    .direction(pair[0], pair[1])
+                    .expect("line is not connected");
+                match (self.from_level(pair[0]), self.from_level(pair[1])) {
+                    (Some(pos), Some(_)) => self.can_see_across(pos, dir),
+                    // The line can clip the ragged edge of a hex map.
+                    _ => true,
+                }
+            });
+        }
+
+        let (origin, target) = (self.to_level(from), self.to_level_near(from, to));
+        let (dx, dy) = (target.0 - origin.0, target.1 - origin.1);
+        let (nx, ny) = (dx.abs(), dy.abs());
+        let xdir = if dx > 0 { 0 } else { 2 };
+        let ydir = if dy > 0 { 1 } else { 3 };
+
+        let mut pos = from;
+        let (mut ix, mut iy) = (0, 0);
+        while ix < nx || iy < ny {
+            let decision = (1 + 2 * ix) * ny - (1 + 2 * iy) * nx;
+            let dir = if decision == 0 {
+                let around = {
+                    let corner = |a: usize, b: usize| {
+                        self.can_see_across(pos, a) &&
+                        self.neighbor(pos, a).map_or(false, |p| self.can_see_across(p, b))
+                    };
+                    corner(xdir, ydir) || corner(ydir, xdir)
+                };
+                if !around {
+                    return false;
+                }
+                pos = self.neighbor(pos, xdir).expect("line of sight left the map");
+                ix += 1;
+                ydir
+            } else if decision < 0 {
+                if !self.can_see_across(pos, xdir) {
+                    return false;
+                }
+                xdir
+            } else {
+                if !self.can_see_across(pos, ydir) {
+                    return false;
+                }
+                ydir
+            };
+            pos = self.neighbor(pos, dir).expect("line of sight left the map");
+            if dir == xdir {
+                ix += 1;
+            } else {
+                iy += 1;
+            }
+        }
+        true
+    }
+
+    /// Returns the tile diagonally across the corner of `pos` between
+    /// directions `a` and `b`, if a unit could walk around the corner
+    /// either way.
+    fn diagonal_neighbor(&self, pos: (u32, u32), a: usize, b: usize) -> Option<(u32, u32)> {
+        let around = |a: usize, b: usize| {
+            self.can_move_across(pos, a) &&
+            self.neighbor(pos, a).map_or(false, |p| self.can_move_across(p, b))
+        };
+        if around(a, b) || around(b, a) {
+            self.neighbor(pos, a).and_then(|p| self.neighbor(p, b))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the tiles next to `pos`, including the diagonal ones if
+    /// units can step diagonally.
+    pub fn adjacent(&self, pos: (u32, u32)) -> Vec<(u32, u32)> {
+        let mut tiles = (0..self.topology.direction_count())
+            .filter_map(|dir| self.neighbor(pos, dir))
+            .collect::<Vec<_>>();
+        if let (Some(_), Topology::Square) = (self.diagonal_cost, self.topology) {
+            for &(a, b) in &DIAGONALS {
+                if let Some(p) = self.neighbor(pos, a).and_then(|p| self.neighbor(p, b)) {
+                    tiles.push(p);
+                }
+            }
+        }
+        tiles
+    }
+
+    /// Returns whether an enemy of `unit` is next to `pos`.
+    fn in_enemy_zone(&self, unit: &Unit, pos: (u32, u32)) -> bool {
+        self.adjacent(pos)
+            .into_iter()
+            .any(|p| self.unit(p).map_or(false, |other| unit.can_attack(other)))
+    }
+
+    /// Returns whether the unit has to stop its movement at `pos` because
+    /// of an enemy's zone of control.
+    #[inline]
+    pub fn stops_in(&self, unit: &Unit, pos: (u32, u32)) -> bool {
+        unit.kind.zone_of_control == ZoneOfControl::Stop && self.in_enemy_zone(unit, pos)
+    }
+
+    /// Returns the extra cost for the unit of entering `pos` because of an
+    /// enemy's zone of control.
+    #[inline]
+    fn zone_cost(&self, unit: &Unit, pos: (u32, u32)) -> u32 {
+        match unit.kind.zone_of_control {
+            ZoneOfControl::Cost(cost) if self.in_enemy_zone(unit, pos) => cost,
+            _ => 0,
+        }
+    }
+
+    /// Returns the tiles a unit can step to from `pos`, the direction of
+    /// each step (`None` for teleporters), and how much it costs.
+    ///
+    /// Diagonal steps are numbered after the directions of the topology.
+    fn steps(&self, unit: &Unit, pos: (u32, u32)) -> Vec<((u32, u32), Option<usize>, u32)> {
+        let count = self.topology.direction_count();
+        let mut steps = Vec::with_capacity(count + DIAGONALS.len() + 1);
+        for dir in 0..count {
+            let npos = match self.neighbor(pos, dir) {
+                Some(npos) if self.can_move_across(pos, dir) => npos,
+                _ => continue,
+            };
+            let cost = unit.terrain_cost(&self.tile(npos).terrain)
+                .saturating_add(self.climb_cost(pos, npos))
+                .saturating_add(self.zone_cost(unit, npos));
+            steps.push((npos, Some(dir), cost));
+        }
+        if let (Some(extra), Topology::Square) = (self.diagonal_cost, self.topology) {
+            for (i, &(a, b)) in DIAGONALS.iter().enumerate() {
+                if let Some(npos) = self.diagonal_neighbor(pos, a, b) {
+                    let cost = unit.terrain_cost(&self.tile(npos).terrain)
+                        .saturating_add(extra)
+                        .saturating_add(self.climb_cost(pos, npos))
+                        .saturating_add(self.zone_cost(unit, npos));
+                    steps.push((npos, Some(count + i), cost));
+                }
+            }
+        }
+        if let Some((npos, cost)) = self.teleporter(pos) {
+            steps.push((npos, None, cost));
+        }
+        steps
+    }
+
+    /// Whether the unit can take a single step from `from` to `to`: across
+    /// to a neighbor with no edge in the way, around a corner, or through
+    /// a teleporter.
+    #[inline]
+    pub fn can_step(&self, unit: &Unit, from: (u32, u32), to: (u32, u32)) -> bool {
+        self.steps(unit, from).into_iter().any(|(pos, _, _)| pos == to)
+    }
+
+    /// Returns how much it costs the unit to step from one tile to the
+    /// next along a path.
+    pub fn step_cost(&self, unit: &Unit, from: (u32, u32), to: (u32, u32)) -> u32 {
+        match self.teleporter(from) {
+            Some((dest, cost)) if dest == to => cost,
+            _ => {
+                let extra = if self.is_diagonal(from, to) {
+                    self.diagonal_cost.unwrap_or(0)
+                } else {
+                    0
+                };
+                unit.terrain_cost(&self.tile(to).terrain)
+                    .saturating_add(extra)
+                    .saturating_add(self.climb_cost(from, to))
+                    .saturating_add(self.zone_cost(unit, to))
+            }
+        }
+    }
+
     #[inline]
     fn index(&self, pos: (u32, u32)) -> usize {
         let (x, y) = pos;
@@ -112,120 +616,659 @@ impl Grid {
         *dst = unit;
     }
 
+    /// Marks all units as ready to act again.
+    pub fn ready_units(&mut self) {
+        for unit in self.units_mut() {
+            unit.spent = false;
+        }
+    }
+
+    /// Rolls for how much `damage` varies by luck.
+    fn roll_luck(&mut self, damage: u32) -> u32 {
+        if self.luck == 0 {
+            return damage;
+        }
+        let roll = self.dice.roll(2 * self.luck + 1);
+        damage * (100 - self.luck + roll) / 100
+    }
+
+    /// Deals `damage` to the unit at `pos`, rolling for luck, and returns
+    /// how much health it lost, or `None` if it was destroyed.
+    fn apply_damage(&mut self, pos: (u32, u32), damage: u32) -> Option<u32> {
+        let damage = self.roll_luck(damage);
+        let (destroyed, health_lost) = {
+            let unit = self.unit_mut(pos).expect("no unit to apply damage to");
+            let health = unit.health;
+            (unit.receive_damage(damage), health - unit.health)
+        };
+        if destroyed {
+            self.remove_unit(pos);
+            None
+        } else {
+            Some(health_lost)
+        }
+    }
+
+    fn calculate_damage(&self,
+                        pos: (u32, u32),
+                        target: (u32, u32),
+                        weapon: usize,
+                        retaliating_after: Option<u32>)
+                        -> u32 {
+        let (attacker, tile) = self.unit_and_tile(pos);
+        let attacker = attacker.expect("no attacking unit");
+        let (defender, other_tile) = self.unit_and_tile(target);
+        let defender = defender.expect("no unit to attack");
+        let terrain = &tile.terrain;
+        let defense = defender.defense_bonus(&other_tile.terrain);
+        let defense = if defense > 0 {
+            defense * (100 - self.facing_penalty(pos, target) as i32) / 100
+        } else {
+            defense
+        };
+        let rules = &self.combat;
+        let damage = match retaliating_after {
+            Some(health_lost) => {
+                attacker.retaliation_damage(weapon, health_lost, terrain, defender, defense, rules)
+            }
+            None => attacker.attack_damage(weapon, terrain, defender, defense, rules.formula),
+        };
+        let range = &attacker.kind.weapons[weapon].range;
+        let damage = damage * self.elevation_modifier(range, pos, target) / 100;
+        damage * self.surroundings_modifier(pos, target) / 100
+    }
+
+    /// Returns how much of its defense the unit at `target` loses, in
+    /// percent, when attacked from `pos` beside or behind it.
+    fn facing_penalty(&self, pos: (u32, u32), target: (u32, u32)) -> u32 {
+        let defender = self.unit(target).expect("no unit to attack");
+        let dir = match self.heading(target, pos) {
+            Some(dir) => dir,
+            None => return 0,
+        };
+        let count = self.topology.direction_count();
+        let turn = (dir + count - defender.facing % count) % count;
+        let turn = cmp::min(turn, count - turn);
+        if turn == count / 2 {
+            self.combat.rear_penalty
+        } else if turn == count / 2 - 1 {
+            self.combat.side_penalty
+        } else {
+            0
+        }
+    }
+
+    /// Returns the percentage of the damage of an attack from `pos` on
+    /// `target` that is dealt because of the units around `target`: the
+    /// attacker's allies flank it, and its own friends support it.
+    fn surroundings_modifier(&self, pos: (u32, u32), target: (u32, u32)) -> u32 {
+        let attacker = self.unit(pos).expect("no attacking unit");
+        let defender = self.unit(target).expect("no unit to attack");
+        let (mut allies, mut friends) = (0, 0);
+        for p in self.adjacent(target) {
+            match self.unit(p) {
+                Some(other) if p != pos && !attacker.can_attack(other) => allies += 1,
+                Some(other) if !defender.can_attack(other) => friends += 1,
+                _ => {}
+            }
+        }
+        let pincer = self.opposite(pos, target)
+            .and_then(|p| self.unit(p))
+            .map_or(false, |other| !attacker.can_attack(other));
+
+        let bonus = 100 + self.combat.flanking * allies +
+                    if pincer { self.combat.pincer } else { 0 };
+        bonus.saturating_sub(self.combat.support * friends)
+    }
+
+    /// Returns the tile on the other side of `target` from `pos`, if they
+    /// are next to each other.
+    fn opposite(&self, pos: (u32, u32), target: (u32, u32)) -> Option<(u32, u32)> {
+        match self.direction(target, pos) {
+            Some(dir) => self.neighbor(target, self.topology.opposite(dir)),
+            None if self.is_diagonal(target, pos) => {
+                let (x, y) = self.to_level(target);
+                let (px, py) = self.to_level_near(target, pos);
+                self.from_level((2 * x - px, 2 * y - py))
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the percentage of the damage of an attack from `pos` on
+    /// `target` that is dealt because of their difference in height.
+    fn elevation_modifier(&self, range: &RangeKind, pos: (u32, u32), target: (u32, u32)) -> u32 {
+        let height = self.height_above(pos, target);
+        let ranged = match *range {
+            RangeKind::Ranged { .. } |
+            RangeKind::Area { .. } => true,
+            _ => false,
+        };
+        if height > 0 && ranged {
+            100 + self.elevation.attack * height as u32
+        } else if height < 0 {
+            100u32.saturating_sub(self.elevation.uphill * -height as u32)
+        } else {
+            100
+        }
+    }
+
+    /// Returns the tiles an attack by `unit` with `weapon` on `target`
+    /// hits, and what percentage of the full damage each takes.
+    pub fn blast_area(&self,
+                      unit: &Unit,
+                      weapon: usize,
+                      target: (u32, u32))
+                      -> Vec<((u32, u32), u32)> {
+        let (radius, metric, falloff) = match unit.kind.weapons[weapon].range {
+            RangeKind::Area { radius, metric, falloff, .. } => (radius, metric, falloff),
+            _ => return vec![(target, 100)],
+        };
+        let center = self.to_level(target);
+        let mut area = Vec::new();
+        for r in 0..radius + 1 {
+            let share = 100u32.saturating_sub(falloff * r);
+            if share == 0 {
+                break;
+            }
+            for pos in self.topology.ring(center, r, metric) {
+                if let Some(pos) = self.from_level(pos) {
+                    if !area.iter().any(|&(p, _)| p == pos) {
+                        area.push((pos, share));
+                    }
+                }
+            }
+        }
+        area
+    }
+
+    /// Returns the enemies a spear thrust from `pos` at `target` hits on
+    /// its way there, including the target, and what percentage of the
+    /// full damage each takes.
+    fn spear_hits(&self,
+                  pos: (u32, u32),
+                  weapon: usize,
+                  target: (u32, u32))
+                  -> Vec<((u32, u32), u32)> {
+        let unit = self.unit(pos).expect("no unit to attack with");
+        let (range, falloff) = match unit.kind.weapons[weapon].range {
+            RangeKind::Spear { range, ref pierce } => (range, pierce.falloff),
+            _ => return vec![(target, 100)],
+        };
+        for dir in 0..self.topology.direction_count() {
+            let mut hits = Vec::new();
+            let mut cur = pos;
+            for _ in 0..range {
+                cur = match self.neighbor(cur, dir) {
+                    Some(next) if self.can_melee_across(cur, dir) => next,
+                    _ => break,
+                };
+                let pierced = hits.len() as u32;
+                let through = match self.unit(cur) {
+                    Some(other) => {
+                        if unit.can_attack(other) {
+                            hits.push((cur, 100u32.saturating_sub(falloff * pierced)));
+                        }
+                        unit.can_spear_through(weapon, other, pierced)
+                    }
+                    None => true,
+                };
+                if cur == target {
+                    return hits;
+                }
+                if !through {
+                    break;
+                }
+            }
+        }
+        vec![(target, 100)]
+    }
+
+    /// Returns the units an attack from `pos` on `target` with `weapon`
+    /// hits, and the damage each takes.
+    fn attack_hits(&self,
+                   pos: (u32, u32),
+                   target: (u32, u32),
+                   weapon: usize)
+                   -> Vec<((u32, u32), u32)> {
+        let attacker = self.unit(pos).expect("no attacking unit");
+        let damage = |(p, share): ((u32, u32), u32)| {
+            (p, self.calculate_damage(pos, p, weapon, None) * share / 100)
+        };
+        match attacker.kind.weapons[weapon].range {
+            RangeKind::Area { friendly_fire, .. } => {
+                let hit = |other: &Unit| friendly_fire || attacker.can_attack(other);
+                self.blast_area(attacker, weapon, target)
+                    .into_iter()
+                    .filter(|&(p, _)| p != pos && self.unit(p).map_or(false, &hit))
+                    .map(&damage)
+                    .collect()
+            }
+            _ => self.spear_hits(pos, weapon, target).into_iter().map(&damage).collect(),
+        }
+    }
+
+    /// Returns the damage the unit at `target` deals back to the unit at
+    /// `pos` with its best weapon after taking `damage_taken`, if it can
+    /// reach it.
+    fn retaliation(&self, pos: (u32, u32), target: (u32, u32), health_lost: u32) -> Option<u32> {
+        let defender = self.unit(target).expect("no retaliating unit");
+        (0..defender.kind.weapons.len())
+            .filter(|&w| self.attack_range_when_retaliating(defender, w, target).any(|p| p == pos))
+            .map(|w| self.calculate_damage(target, pos, w, Some(health_lost)))
+            .max()
+    }
+
+    /// Returns what an attack from `pos` on the unit at `target` with
+    /// `weapon` could do to it, if anything. The target striking first is
+    /// not taken into account.
+    pub fn forecast(&self,
+                    pos: (u32, u32),
+                    target: (u32, u32),
+                    weapon: usize)
+                    -> Option<Forecast> {
+        let health = match self.unit(target) {
+            Some(unit) => unit.health,
+            None => return None,
+        };
+        let hits = self.attack_hits(pos, target, weapon);
+        let damage = match hits.into_iter().find(|&(p, _)| p == target) {
+            Some((_, damage)) => damage,
+            None => return None,
+        };
+        let points = (0..2 * self.luck + 1)
+            .map(|roll| (damage * (100 - self.luck + roll) / 100 + 5) / 10)
+            .collect::<Vec<_>>();
+        let kills = points.iter().filter(|&&p| p >= health).count() as u32;
+        Some(Forecast {
+            min: points[0],
+            max: points[points.len() - 1],
+            kill_chance: kills * 100 / points.len() as u32,
+        })
+    }
+
+    /// Lets the unit at `pos` attack the unit at `target` with `weapon`.
+    /// The target retaliates with its best weapon if it survives and can
+    /// reach the attacker, or strikes first if it has first strike.
+    ///
+    /// Spears also hit the enemies they pierce on the way. Area attacks
+    /// instead hit every unit around `target` at once, and nobody
+    /// retaliates.
+    pub fn attack(&mut self, pos: (u32, u32), target: (u32, u32), weapon: usize) {
+        let (can_retaliate, first_strike) = {
+            let attacker = self.unit(pos).expect("no attacking unit");
+            let defender = self.unit(target).expect("no unit to attack");
+            let can_retaliate = match attacker.kind.weapons[weapon].range {
+                RangeKind::Area { .. } => false,
+                _ => true,
+            };
+            (can_retaliate, can_retaliate && defender.kind.first_strike)
+        };
+
+        if first_strike {
+            if let Some(damage) = self.retaliation(pos, target, 0) {
+                // Destroyed attacker cannot strike at all.
+                if self.apply_damage(pos, damage).is_none() {
+                    return;
+                }
+            }
+        }
+
+        // All damage is worked out before any is dealt.
+        let mut target_health_lost = None;
+        for (p, damage) in self.attack_hits(pos, target, weapon) {
+            // Destroyed defender cannot retaliate.
+            let health_lost = self.apply_damage(p, damage);
+            if p == target {
+                target_health_lost = health_lost;
+            }
+        }
+        let health_lost = match target_health_lost {
+            Some(health_lost) if can_retaliate && !first_strike => health_lost,
+            _ => return,
+        };
+        if let Some(damage) = self.retaliation(pos, target, health_lost) {
+            self.apply_damage(pos, damage);
+        }
+    }
+
+    /// Returns whether the unit at `pos` is able to capture the tile it stands on.
+    pub fn can_capture(&self, pos: (u32, u32)) -> bool {
+        let (unit, tile) = self.unit_and_tile(pos);
+        let unit = unit.expect("no unit to capture with");
+        unit.kind.capture > 0 && tile.can_be_captured() && tile.faction != Some(unit.faction)
+    }
+
+    /// Lets the unit at `pos` capture the tile it stands on.
+    ///
+    /// Returns whether the tile changed owner.
+    pub fn capture(&mut self, pos: (u32, u32)) -> bool {
+        let (unit, tile) = self.unit_and_tile_mut(pos);
+        let unit = unit.expect("no unit to capture with");
+        assert!(tile.can_be_captured() && tile.faction != Some(unit.faction));
+
+        let capture = unit.kind.capture * unit.health / 10;
+        info!("Trying to capture {:?} with strength {:?}", tile.terrain.name, capture);
+        if tile.capture(unit.faction, capture) {
+            info!("Tile at {:?} captured by {:?}!", pos, unit.faction);
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn attack_range_before_moving<'a>(&'a self,
                                           unit: &'a Unit,
+                                          weapon: usize,
                                           pos: (u32, u32))
                                           -> AttackRange<'a> {
-        match unit.kind.attack.range {
+        match unit.kind.weapons[weapon].range {
             RangeKind::Melee => AttackRange::melee(self, pos),
-            RangeKind::Ranged { min, max } => AttackRange::ranged(self, pos, min, max),
-            RangeKind::Spear { range } => AttackRange::spear(self, unit, pos, range),
+            RangeKind::Ranged { min, max, metric } => {
+                AttackRange::ranged(self, pos, min, max, metric)
+            }
+            RangeKind::Spear { range, .. } => AttackRange::spear(self, unit, weapon, pos, range),
+            RangeKind::Area { min, max, metric, .. } => {
+                AttackRange::area(self, pos, min, max, metric)
+            }
         }
     }
 
     pub fn attack_range_after_moving<'a>(&'a self,
                                          unit: &'a Unit,
+                                         weapon: usize,
                                          pos: (u32, u32))
                                          -> AttackRange<'a> {
-        match unit.kind.attack.range {
+        match unit.kind.weapons[weapon].range {
             RangeKind::Melee |
             RangeKind::Spear { .. } => AttackRange::melee(self, pos),
-            RangeKind::Ranged { .. } => AttackRange::empty(),
+            RangeKind::Ranged { .. } |
+            RangeKind::Area { .. } => AttackRange::empty(),
         }
     }
 
     pub fn attack_range_when_retaliating<'a>(&'a self,
                                              unit: &'a Unit,
+                                             weapon: usize,
                                              pos: (u32, u32))
                                              -> AttackRange<'a> {
-        self.attack_range_before_moving(unit, pos)
+        match unit.kind.weapons[weapon].range {
+            // Shelling a neighborhood is no way to fight back.
+            RangeKind::Area { .. } => AttackRange::empty(),
+            RangeKind::Ranged { .. } if !self.combat.ranged_retaliation => AttackRange::empty(),
+            _ => self.attack_range_before_moving(unit, weapon, pos),
+        }
     }
 
-    pub fn find_attackable_before_moving<'a>(&'a self,
-                                             unit: &'a Unit,
-                                             pos: (u32, u32))
-                                             -> FindAttackable<'a> {
-        FindAttackable {
-            unit: unit,
-            grid: self,
-            range: self.attack_range_before_moving(unit, pos),
-        }
+    /// Finds what the unit can attack from `pos` with any of its weapons,
+    /// depending on whether it `moved` there.
+    pub fn find_attackable<'a>(&'a self,
+                               unit: &'a Unit,
+                               pos: (u32, u32),
+                               moved: bool)
+                               -> FindAttackable<'a> {
+        let ranges = (0..unit.kind.weapons.len())
+            .map(|w| (w, self.attack_range(unit, w, pos, moved)))
+            .collect();
+        FindAttackable::new(self, unit, ranges)
     }
 
-    pub fn find_attackable_after_moving<'a>(&'a self,
-                                            unit: &'a Unit,
-                                            pos: (u32, u32))
-                                            -> FindAttackable<'a> {
-        FindAttackable {
-            unit: unit,
-            grid: self,
-            range: self.attack_range_after_moving(unit, pos),
+    /// Finds what the unit can attack from `pos` with the weapon numbered
+    /// `weapon`, depending on whether it `moved` there.
+    pub fn find_attackable_with<'a>(&'a self,
+                                    unit: &'a Unit,
+                                    weapon: usize,
+                                    pos: (u32, u32),
+                                    moved: bool)
+                                    -> FindAttackable<'a> {
+        let range = self.attack_range(unit, weapon, pos, moved);
+        FindAttackable::new(self, unit, vec![(weapon, range)])
+    }
+
+    #[inline]
+    fn attack_range<'a>(&'a self,
+                        unit: &'a Unit,
+                        weapon: usize,
+                        pos: (u32, u32),
+                        moved: bool)
+                        -> AttackRange<'a> {
+        if moved {
+            self.attack_range_after_moving(unit, weapon, pos)
+        } else {
+            self.attack_range_before_moving(unit, weapon, pos)
         }
     }
 
+    #[inline]
     pub fn path_finder(&self, pos: (u32, u32)) -> PathFinder {
+        self.path_finder_with(pos, PathPreference::Straight)
+    }
+
+    /// Finds the cheapest path to every tile the unit at `pos` can reach
+    /// this turn, choosing between equally cheap paths by `preference`.
+    ///
+    /// Enemies hinder the unit according to its zone of control rule: it
+    /// may have to stop next to them, or pay extra to pass.
+    pub fn path_finder_with(&self, pos: (u32, u32), preference: PathPreference) -> PathFinder {
         let unit = self.unit(pos).expect("no unit to find path for");
-        let mut to_be_searched = vec![(pos, 0u32)];
-        let mut costs = BTreeMap::new();
-        let (w, h) = self.size();
+        let origin = pos;
 
-        while let Some((pos, cost)) = to_be_searched.pop() {
-            match costs.entry(pos) {
-                btree_map::Entry::Vacant(entry) => {
-                    entry.insert(cost);
-                }
-                btree_map::Entry::Occupied(mut entry) => {
-                    if *entry.get() > cost {
-                        entry.insert(cost);
-                    } else {
+        // The best (cost, bends, direction) found so far for each tile.
+        let mut labels = BTreeMap::new();
+        let mut predecessors = BTreeMap::new();
+        let mut to_be_searched = BinaryHeap::new();
+        labels.insert(origin, (0u32, 0u32, None));
+        to_be_searched.push(Node {
+            cost: 0,
+            bends: 0,
+            pos: origin,
+        });
+
+        while let Some(Node { cost, bends, pos }) = to_be_searched.pop() {
+            let (best_cost, best_bends, dir) = labels[&pos];
+            if (cost, bends) > (best_cost, best_bends) {
+                // Already reached more cheaply.
+                continue;
+            }
+            if pos != origin && self.stops_in(unit, pos) {
+                continue;
+            }
+
+            for (npos, ndir, tcost) in self.steps(unit, pos) {
+                if let Some(other) = self.unit(npos) {
+                    if !unit.can_move_through(other) {
                         continue;
                     }
                 }
-            }
 
-            let mut dir = 0;
-            loop {
-                let (dx, dy) = match dir {
-                    0 => (1, 0),
-                    1 => (0, 1),
-                    2 => (-1, 0),
-                    3 => (0, -1),
-                    _ => break,
+                let ncost = cost.saturating_add(tcost);
+                if ncost > unit.kind.movement.movement {
+                    continue;
+                }
+
+                let nbends = match preference {
+                    PathPreference::Straight if dir.is_some() && dir != ndir => bends + 1,
+                    _ => bends,
                 };
-                dir += 1;
+                let better = labels.get(&npos)
+                    .map_or(true, |&(c, b, _)| (ncost, nbends) < (c, b));
+                if better {
+                    labels.insert(npos, (ncost, nbends, ndir));
+                    predecessors.insert(npos, pos);
+                    to_be_searched.push(Node {
+                        cost: ncost,
+                        bends: nbends,
+                        pos: npos,
+                    });
+                }
+            }
+        }
 
-                let nx = pos.0 as i32 + dx;
-                let ny = pos.1 as i32 + dy;
+        PathFinder {
+            origin: origin,
+            costs: labels.into_iter().map(|(pos, (cost, _, _))| (pos, cost)).collect(),
+            predecessors: predecessors,
+        }
+    }
 
-                if nx < 0 || w as i32 <= nx || ny < 0 || h as i32 <= ny {
-                    continue;
-                }
+    /// Finds the cheapest route for the unit at `pos` to `target`, no
+    /// matter how many turns it takes. Enemy units block the way, but
+    /// the target itself may be occupied.
+    pub fn route(&self, pos: (u32, u32), target: (u32, u32)) -> Option<Route> {
+        let unit = self.unit(pos).expect("no unit to find route for");
+        let movement = unit.kind.movement.movement;
 
-                let npos = (nx as u32, ny as u32);
+        // Never overestimate, or A* may miss the cheapest route. Teleporters
+        // can lead anywhere, so with any of them around, nothing is certain.
+        let min_cost = if self.teleporters.is_empty() {
+            unit.kind.movement.class.costs.values().cloned().min().unwrap_or(0)
+        } else {
+            0
+        };
+        let metric = if self.diagonal_cost.is_some() {
+            Metric::Chebyshev
+        } else {
+            Metric::Manhattan
+        };
+        let estimate = |pos: (u32, u32)| {
+            self.topology
+                .measure(self.to_level(pos), self.to_level_near(pos, target), metric)
+                .saturating_mul(min_cost)
+        };
 
-                let (other, tile) = self.unit_and_tile(npos);
+        let mut costs = BTreeMap::new();
+        let mut predecessors = BTreeMap::new();
+        let mut to_be_searched = BinaryHeap::new();
+        costs.insert(pos, 0u32);
+        to_be_searched.push(Node {
+            cost: estimate(pos),
+            bends: 0,
+            pos: pos,
+        });
 
-                if let Some(other) = other {
-                    if !unit.can_move_through(other) {
+        while let Some(Node { pos: current, .. }) = to_be_searched.pop() {
+            if current == target {
+                break;
+            }
+            let cost = costs[&current];
+
+            for (npos, _, tcost) in self.steps(unit, current) {
+                if let Some(other) = self.unit(npos) {
+                    if npos != target && !unit.can_move_through(other) {
                         continue;
                     }
                 }
 
-                let tcost = unit.terrain_cost(&tile.terrain);
-                if tcost == 0 {
-                    unimplemented!();
+                if tcost > movement {
+                    continue;
                 }
                 let ncost = cost.saturating_add(tcost);
-
-                if ncost <= unit.kind.movement.movement {
-                    to_be_searched.push((npos, ncost));
+                if costs.get(&npos).map_or(true, |&c| ncost < c) {
+                    costs.insert(npos, ncost);
+                    predecessors.insert(npos, current);
+                    to_be_searched.push(Node {
+                        cost: ncost.saturating_add(estimate(npos)),
+                        bends: 0,
+                        pos: npos,
+                    });
                 }
             }
         }
-        PathFinder {
-            origin: pos,
-            costs: costs,
+
+        let cost = match costs.get(&target) {
+            Some(&cost) => cost,
+            None => return None,
+        };
+        let mut path = Vec::new();
+        let mut current = target;
+        while current != pos {
+            path.push(current);
+            current = predecessors[&current];
+        }
+        path.reverse();
+
+        // Count the turns by walking the route with a fresh budget each
+        // turn. Stopping in an enemy's zone of control ends a turn early.
+        let mut turns = 0;
+        let mut left = 0;
+        let mut stopped = false;
+        let mut prev = pos;
+        for &step in &path {
+            let tcost = self.step_cost(unit, prev, step);
+            prev = step;
+            if stopped || tcost > left || turns == 0 {
+                turns += 1;
+                left = movement;
+            }
+            left -= tcost;
+            stopped = self.stops_in(unit, step);
+        }
+
+        Some(Route {
+            path: path,
+            cost: cost,
+            turns: turns,
+        })
+    }
+
+    /// Returns how far the unit at `pos` can get towards `target` along
+    /// its route this turn, or `None` if there is no route.
+    pub fn step_towards(&self, pos: (u32, u32), target: (u32, u32)) -> Option<(u32, u32)> {
+        let route = match self.route(pos, target) {
+            Some(route) => route,
+            None => return None,
+        };
+        let path_finder = self.path_finder(pos);
+        let mut dest = pos;
+        for step in route.path {
+            if !path_finder.can_move_to(step) {
+                break;
+            }
+            if self.unit(step).is_none() {
+                dest = step;
+            }
         }
+        Some(dest)
+    }
+}
+
+
+/// How to choose between paths that cost the same.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathPreference {
+    /// Prefer paths that change direction as few times as possible.
+    Straight,
+    /// Take the first path found, trying directions in a fixed order.
+    First,
+}
+
+/// A route that may take several turns to walk.
+#[derive(Clone, Debug)]
+pub struct Route {
+    /// The tiles along the route, excluding the origin.
+    pub path: Vec<(u32, u32)>,
+    pub cost: u32,
+    pub turns: u32,
+}
+
+/// An entry in the path finder's queue, ordered so that the cheapest
+/// entry is popped first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Node {
+    cost: u32,
+    bends: u32,
+    pos: (u32, u32),
+}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Node) -> Ordering {
+        (other.cost, other.bends, other.pos).cmp(&(self.cost, self.bends, self.pos))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
@@ -279,6 +1322,8 @@ impl<'a> Iterator for UnitsMut<'a> {
 pub struct PathFinder {
     origin: (u32, u32),
     costs: BTreeMap<(u32, u32), u32>,
+    /// The previous tile on the chosen path to each reachable tile.
+    predecessors: BTreeMap<(u32, u32), (u32, u32)>,
 }
 
 impl PathFinder {
@@ -292,92 +1337,808 @@ impl PathFinder {
         self.costs.get(&pos).cloned()
     }
 
+    /// Returns every position the unit can move to, including its own.
+    #[inline]
+    pub fn reachable(&self) -> Vec<(u32, u32)> {
+        self.costs.keys().cloned().collect()
+    }
+
     pub fn total_attack_range(&self, grid: &Grid) -> BTreeSet<(u32, u32)> {
         let unit = grid.unit(self.origin).expect("no unit to find attackable targets for");
 
         let mut set = BTreeSet::new();
 
-        set.extend(grid.attack_range_before_moving(unit, self.origin));
+        for weapon in 0..unit.kind.weapons.len() {
+            set.extend(grid.attack_range_before_moving(unit, weapon, self.origin));
 
-        // This is probably a somewhat ineffecient algorithm.
-        for &pos in self.costs.keys() {
-            if grid.unit(pos).is_some() {
-                continue;
+            // This is probably a somewhat ineffecient algorithm.
+            for &pos in self.costs.keys() {
+                if grid.unit(pos).is_some() {
+                    continue;
+                }
+                set.extend(grid.attack_range_after_moving(unit, weapon, pos));
             }
-            set.extend(grid.attack_range_after_moving(unit, pos));
         }
         set
     }
 
-    #[inline]
-    pub fn random_path_rev(&self, target: (u32, u32)) -> RandomPathRev {
-        RandomPathRev {
-            path_finder: self,
-            pos: target,
-        }
-    }
-}
-
-#[derive(Clone)]
-pub struct RandomPathRev<'a> {
-    path_finder: &'a PathFinder,
-    pos: (u32, u32),
-}
-
-impl<'a> Iterator for RandomPathRev<'a> {
-    type Item = (u32, u32);
-
-    fn next(&mut self) -> Option<(u32, u32)> {
-        if self.pos == self.path_finder.origin {
+    /// Returns the path to `target`, excluding the origin, or `None` if
+    /// the unit cannot move there.
+    pub fn path(&self, target: (u32, u32)) -> Option<Vec<(u32, u32)>> {
+        if !self.can_move_to(target) {
             return None;
         }
-
-        let cost = self.path_finder.cost(self.pos).expect("invalid position");
-
-        let mut rng = thread_rng();
-        let mut adjacent = [(0, 1), (1, 0), (0, -1), (-1, 0)];
-        rng.shuffle(&mut adjacent);
-
-        let mut res = None;
-        let mut cost = cost;
-        for &(dx, dy) in &adjacent {
-            let x = self.pos.0 as i32 + dx;
-            let y = self.pos.1 as i32 + dy;
-
-            if x < 0 || y < 0 {
-                continue;
-            }
-            let npos = (x as u32, y as u32);
-            if let Some(ncost) = self.path_finder.cost(npos) {
-                if ncost < cost {
-                    res = Some(npos);
-                    cost = ncost;
-                }
-            }
+        let mut path = Vec::new();
+        let mut pos = target;
+        while pos != self.origin {
+            path.push(pos);
+            pos = self.predecessors[&pos];
         }
-        let item = self.pos;
-        self.pos = res.expect("path finder somehow produced a local minimum!");
-        Some(item)
+        path.reverse();
+        Some(path)
     }
 }
 
+/// Finds the targets in reach of any of several weapons, each only once.
 pub struct FindAttackable<'a> {
     unit: &'a Unit,
     grid: &'a Grid,
-    range: AttackRange<'a>,
+    /// The range of each weapon, with its number.
+    ranges: Vec<(usize, AttackRange<'a>)>,
+    /// The index of the range being searched.
+    current: usize,
+    found: BTreeSet<(u32, u32)>,
+}
+
+impl<'a> FindAttackable<'a> {
+    fn new(grid: &'a Grid,
+           unit: &'a Unit,
+           ranges: Vec<(usize, AttackRange<'a>)>)
+           -> FindAttackable<'a> {
+        FindAttackable {
+            unit: unit,
+            grid: grid,
+            ranges: ranges,
+            current: 0,
+            found: BTreeSet::new(),
+        }
+    }
 }
 
 impl<'a> Iterator for FindAttackable<'a> {
     type Item = (u32, u32);
 
     fn next(&mut self) -> Option<(u32, u32)> {
-        for pos in &mut self.range {
-            if let Some(ref other) = self.grid.unit(pos) {
-                if self.unit.can_attack(other) {
+        let (unit, grid) = (self.unit, self.grid);
+        while self.current < self.ranges.len() {
+            let weapon = self.ranges[self.current].0;
+            while let Some(pos) = self.ranges[self.current].1.next() {
+                // Area attacks can target any tile, as long as they hit an enemy.
+                let hits_enemy = grid.blast_area(unit, weapon, pos)
+                    .into_iter()
+                    .any(|(p, _)| grid.unit(p).map_or(false, |other| unit.can_attack(other)));
+                if hits_enemy && self.found.insert(pos) {
                     return Some(pos);
                 }
             }
+            self.current += 1;
         }
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+    use std::rc::Rc;
+
+    use dice::Dice;
+    use edge::EdgeKind;
+    use faction::Faction;
+    use fixtures;
+    use info::DamageFormula;
+    use range::AttackRange;
+    use topology::{Metric, Wrap};
+
+    use super::PathPreference;
+
+    /// Counts how often a path changes direction, origin included.
+    fn bends(origin: (u32, u32), path: &[(u32, u32)]) -> usize {
+        let mut prev = origin;
+        let steps = path.iter()
+            .map(|&pos| {
+                let step = (pos.0 as i32 - prev.0 as i32, pos.1 as i32 - prev.1 as i32);
+                prev = pos;
+                step
+            })
+            .collect::<Vec<_>>();
+        steps.windows(2).filter(|pair| pair[0] != pair[1]).count()
+    }
+
+    #[test]
+    fn path_goes_around_obstacles() {
+        let grid = fixtures::grid(&[".m..", ".m.m", "...m"], &[("warrior", (0, 0), 1)]);
+        let path_finder = grid.path_finder((0, 0));
+        assert_eq!(path_finder.path((2, 0)),
+                   Some(vec![(0, 1), (0, 2), (1, 2), (2, 2), (2, 1), (2, 0)]));
+        assert_eq!(path_finder.cost((2, 0)), Some(6));
+        assert_eq!(path_finder.path((3, 0)), None);
+        assert_eq!(path_finder.path((0, 0)), Some(vec![]));
+    }
+
+    #[test]
+    fn path_avoids_woods_when_cheaper() {
+        let grid = fixtures::grid(&["..", "w.", "w.", "w.", ".."], &[("warrior", (0, 0), 1)]);
+        let path_finder = grid.path_finder((0, 0));
+        assert_eq!(path_finder.path((0, 4)),
+                   Some(vec![(1, 0), (1, 1), (1, 2), (1, 3), (1, 4), (0, 4)]));
+        assert_eq!(path_finder.cost((0, 4)), Some(6));
+    }
+
+    #[test]
+    fn straight_paths_win_ties() {
+        // Around the woods costs as much as through them, with two bends.
+        let grid = fixtures::grid(&["....", ".ww.", "...."], &[("warrior", (0, 1), 1)]);
+        let path_finder = grid.path_finder_with((0, 1), PathPreference::Straight);
+        assert_eq!(path_finder.cost((3, 1)), Some(5));
+        assert_eq!(path_finder.path((3, 1)), Some(vec![(1, 1), (2, 1), (3, 1)]));
+
+        // Every shortest path to the opposite corner bends at least once.
+        let grid = fixtures::grid(&["...", "...", "..."], &[("warrior", (0, 0), 1)]);
+        let path_finder = grid.path_finder_with((0, 0), PathPreference::Straight);
+        let path = path_finder.path((2, 2)).unwrap();
+        assert_eq!(path.len(), 4);
+        assert_eq!(bends((0, 0), &path), 1);
+    }
+
+    #[test]
+    fn routes_end_turns_in_zones_of_control() {
+        let rows = [".......", "......."];
+        let grid = fixtures::grid(&rows, &[("warrior", (0, 0), 1)]);
+        let route = grid.route((0, 0), (6, 0)).unwrap();
+        assert_eq!((route.cost, route.turns), (6, 1));
+
+        // Every way past the enemy leads next to it.
+        let grid = fixtures::grid(&rows, &[("warrior", (0, 0), 1), ("warrior", (2, 1), 2)]);
+        let route = grid.route((0, 0), (6, 0)).unwrap();
+        assert_eq!((route.cost, route.turns), (6, 2));
+    }
+
+    #[test]
+    fn walls_block_steps() {
+        let mut grid = fixtures::grid(&["...", "..."], &[("warrior", (0, 0), 1)]);
+        grid.set_edge((0, 0), 0, Some(EdgeKind::Wall));
+        let unit = grid.unit((0, 0)).unwrap().clone();
+        assert!(!grid.can_step(&unit, (0, 0), (1, 0)));
+        assert!(!grid.can_step(&unit, (1, 0), (0, 0)));
+        assert!(grid.can_step(&unit, (0, 0), (0, 1)));
+        assert!(!grid.can_step(&unit, (0, 0), (2, 0)));
+        assert_eq!(grid.path_finder((0, 0)).path((1, 0)),
+                   Some(vec![(0, 1), (1, 1), (1, 0)]));
+    }
+
+    #[test]
+    fn retaliation_is_dealt_by_the_retaliating_unit() {
+        let mut grid = fixtures::grid(&["..."], &[("archer", (0, 0), 1), ("warrior", (1, 0), 2)]);
+        grid.unit_mut((1, 0)).unwrap().facing = 2;
+        let mut archer = grid.unit((0, 0)).unwrap().clone();
+        let mut warrior = grid.unit((1, 0)).unwrap().clone();
+        let terrain = grid.tile((0, 0)).terrain.clone();
+
+        // The archer stabs with its dagger; the warrior strikes back with
+        // its own weapon, against the archer's defense.
+        let damage = archer.attack_damage(1,
+                                          &terrain,
+                                          &warrior,
+                                          warrior.defense_bonus(&terrain),
+                                          grid.combat.formula);
+        warrior.receive_damage(damage);
+        let lost = 10 - warrior.health;
+        let retaliation = warrior.retaliation_damage(0,
+                                                     lost,
+                                                     &terrain,
+                                                     &archer,
+                                                     archer.defense_bonus(&terrain),
+                                                     &grid.combat);
+        archer.receive_damage(retaliation);
+        assert!(retaliation != damage);
+
+        grid.attack((0, 0), (1, 0), 1);
+        assert_eq!(grid.unit((1, 0)).unwrap().health, warrior.health);
+        assert_eq!(grid.unit((0, 0)).unwrap().health, archer.health);
+    }
+
+    #[test]
+    fn unscaled_retaliation_recovers_health_lost_to_luck() {
+        for seed in 1..40 {
+            let mut grid = fixtures::grid(&["..."],
+                                          &[("warrior", (0, 0), 1), ("warrior", (1, 0), 2)]);
+            grid.combat.scale_retaliation = false;
+            grid.set_luck(50, Dice::new(seed));
+            grid.unit_mut((1, 0)).unwrap().facing = 2;
+            let attack = grid.calculate_damage((0, 0), (1, 0), 0, None);
+            let retaliation = grid.calculate_damage((1, 0), (0, 0), 0, Some(0));
+
+            let mut dice = Dice::new(seed);
+            let roll = |damage: u32, dice: &mut Dice| {
+                (damage * (50 + dice.roll(101)) / 100 + 5) / 10
+            };
+            let lost = roll(attack, &mut dice);
+            let struck_back = roll(retaliation, &mut dice);
+
+            grid.attack((0, 0), (1, 0), 0);
+            assert_eq!(grid.unit((1, 0)).unwrap().health, 10 - lost);
+            // Struck back as hard as it would have before losing health.
+            assert_eq!(grid.unit((0, 0)).unwrap().health, 10 - struck_back);
+        }
+    }
+
+    /// Returns the facing penalty of an attack from each direction on a
+    /// unit in the middle of a 3x3 map, facing `facing`.
+    fn facing_penalties(topology: Option<&str>, facing: usize) -> Vec<u32> {
+        let rows = ["...", "...", "..."];
+        let center = (1, 1);
+        let grid = fixtures::level(topology, &rows, &[("warrior", center, 2)])
+            .create_grid(&fixtures::info());
+        (0..grid.topology().direction_count())
+            .map(|dir| {
+                let pos = grid.neighbor(center, dir).unwrap();
+                let mut grid = grid.clone();
+                let mut attacker = grid.unit(center).unwrap().clone();
+                attacker.faction = Faction::Red;
+                grid.add_unit(attacker, pos);
+                grid.unit_mut(center).unwrap().facing = facing;
+                grid.facing_penalty(pos, center)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn heading_points_along_lines() {
+        let grid = fixtures::grid(&["....", "....", "...."], &[]);
+        assert_eq!(grid.heading((1, 1), (1, 1)), None);
+        assert_eq!(grid.heading((1, 1), (3, 1)), Some(0));
+        assert_eq!(grid.heading((1, 0), (1, 2)), Some(1));
+        assert_eq!(grid.heading((3, 1), (0, 1)), Some(2));
+        assert_eq!(grid.heading((0, 2), (0, 0)), Some(3));
+
+        let grid = fixtures::level(Some("hex"), &["...", "...", "..."], &[])
+            .create_grid(&fixtures::info());
+        for dir in 0..6 {
+            let pos = grid.neighbor((1, 1), dir).unwrap();
+            assert_eq!(grid.heading((1, 1), pos), Some(dir));
+        }
+    }
+
+    #[test]
+    fn facing_penalty_on_squares() {
+        // Front, side, rear, side.
+        assert_eq!(facing_penalties(None, 0), vec![0, 25, 50, 25]);
+        assert_eq!(facing_penalties(None, 1), vec![25, 0, 25, 50]);
+    }
+
+    #[test]
+    fn facing_penalty_on_hexes() {
+        // Only the front three sides are safe; the next two on either side
+        // are flanks, and the one behind is the rear.
+        assert_eq!(facing_penalties(Some("hex"), 0), vec![0, 0, 25, 50, 25, 0]);
+        assert_eq!(facing_penalties(Some("hex"), 3), vec![50, 25, 0, 0, 0, 25]);
+    }
+
+    #[test]
+    fn path_finder_covers_large_open_maps() {
+        let row = iter::repeat('.').take(100).collect::<String>();
+        let rows = vec![&row[..]; 100];
+        let mut grid = fixtures::grid(&rows, &[("warrior", (50, 50), 1)]);
+        {
+            let unit = grid.unit_mut((50, 50)).unwrap();
+            let mut kind = (*unit.kind).clone();
+            kind.movement.movement = 200;
+            unit.kind = Rc::new(kind);
+        }
+
+        let path_finder = grid.path_finder((50, 50));
+        assert_eq!(path_finder.reachable().len(), 100 * 100);
+        assert_eq!(path_finder.cost((0, 0)), Some(100));
+        assert_eq!(path_finder.cost((99, 99)), Some(98));
+        let path = path_finder.path((0, 0)).unwrap();
+        assert_eq!(path.len(), 100);
+        assert_eq!(bends((50, 50), &path), 1);
+    }
+
+    #[test]
+    fn roads_are_free_to_cross() {
+        let grid = fixtures::grid(&["....", "rrrr"], &[("warrior", (0, 0), 1)]);
+        let path_finder = grid.path_finder((0, 0));
+        assert_eq!(path_finder.cost((3, 1)), Some(0));
+        assert_eq!(path_finder.cost((3, 0)), Some(1));
+        assert_eq!(path_finder.path((3, 0)),
+                   Some(vec![(0, 1), (1, 1), (2, 1), (3, 1), (3, 0)]));
+    }
+
+    #[test]
+    fn teleporters_lead_one_way() {
+        let mut grid = fixtures::grid(&["........"], &[("warrior", (0, 0), 1)]);
+        assert_eq!(grid.path_finder((0, 0)).cost((7, 0)), None);
+
+        grid.set_teleporter((1, 0), (7, 0), 1);
+        let unit = grid.unit((0, 0)).unwrap().clone();
+        assert!(grid.can_step(&unit, (1, 0), (7, 0)));
+        assert!(!grid.can_step(&unit, (7, 0), (1, 0)));
+        let path_finder = grid.path_finder((0, 0));
+        assert_eq!(path_finder.path((7, 0)), Some(vec![(1, 0), (7, 0)]));
+        assert_eq!(path_finder.cost((7, 0)), Some(2));
+        assert_eq!(path_finder.cost((5, 0)), Some(4));
+    }
+
+    #[test]
+    fn free_teleporter_loops_end() {
+        let mut grid = fixtures::grid(&["rrrr"], &[("warrior", (0, 0), 1)]);
+        grid.set_teleporter((0, 0), (3, 0), 0);
+        grid.set_teleporter((3, 0), (0, 0), 0);
+        let path_finder = grid.path_finder((0, 0));
+        assert_eq!(path_finder.reachable().len(), 4);
+        assert_eq!(path_finder.path((3, 0)), Some(vec![(3, 0)]));
+        let route = grid.route((0, 0), (2, 0)).unwrap();
+        assert_eq!((route.cost, route.turns), (0, 1));
+    }
+
+    #[test]
+    fn only_walls_block_line_of_sight() {
+        let mut grid = fixtures::grid(&[".....", ".....", "....."], &[]);
+        assert!(grid.line_of_sight((0, 1), (4, 1)));
+        grid.set_edge((2, 1), 0, Some(EdgeKind::Cliff));
+        assert!(grid.line_of_sight((0, 1), (4, 1)));
+        grid.set_edge((2, 1), 0, Some(EdgeKind::Wall));
+        assert!(!grid.line_of_sight((0, 1), (4, 1)));
+        assert!(!grid.line_of_sight((4, 1), (0, 1)));
+        assert!(grid.line_of_sight((0, 0), (4, 0)));
+    }
+
+    #[test]
+    fn sight_through_a_corner_needs_both_ways_blocked() {
+        let mut grid = fixtures::grid(&["...", "...", "..."], &[]);
+        grid.set_edge((0, 0), 0, Some(EdgeKind::Wall));
+        assert!(grid.line_of_sight((0, 0), (2, 2)));
+        grid.set_edge((0, 0), 1, Some(EdgeKind::Wall));
+        assert!(!grid.line_of_sight((0, 0), (2, 2)));
+    }
+
+    #[test]
+    fn edges_differ_in_what_they_block() {
+        let mut grid = fixtures::grid(&["..", ".."], &[("warrior", (0, 0), 1)]);
+        let unit = grid.unit((0, 0)).unwrap().clone();
+        grid.set_edge((0, 0), 0, Some(EdgeKind::River));
+        assert!(!grid.can_step(&unit, (0, 0), (1, 0)));
+        assert!(grid.can_melee_across((0, 0), 0));
+        grid.set_edge((0, 0), 0, Some(EdgeKind::Cliff));
+        assert!(!grid.can_melee_across((0, 0), 0));
+        assert!(!grid.can_melee_across((1, 0), 2));
+
+        // Ledges can only be jumped down from the tile they belong to.
+        grid.set_edge((0, 0), 0, Some(EdgeKind::Ledge));
+        assert!(grid.can_step(&unit, (0, 0), (1, 0)));
+        assert!(!grid.can_step(&unit, (1, 0), (0, 0)));
+        assert!(grid.can_melee_across((1, 0), 2));
+    }
+
+    #[test]
+    fn hex_neighbors_depend_on_the_row() {
+        let rows = ["....", "....", "....", "...."];
+        let grid = fixtures::level(Some("hex"), &rows, &[]).create_grid(&fixtures::info());
+        assert_eq!(grid.adjacent((1, 1)),
+                   vec![(2, 1), (2, 2), (1, 2), (0, 1), (1, 0), (2, 0)]);
+        assert_eq!(grid.adjacent((1, 2)),
+                   vec![(2, 2), (1, 3), (0, 3), (0, 2), (0, 1), (1, 1)]);
+        assert_eq!(grid.adjacent((0, 0)), vec![(1, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn hex_paths_cut_across_rows() {
+        let rows = ["....", "....", "....", "...."];
+        let grid = fixtures::level(Some("hex"), &rows, &[("warrior", (0, 0), 1)])
+            .create_grid(&fixtures::info());
+        assert_eq!(grid.distance((0, 0), (2, 2)), 3);
+        assert_eq!(grid.distance((0, 0), (3, 3)), 5);
+        let path_finder = grid.path_finder((0, 0));
+        assert_eq!(path_finder.reachable().len(), 16);
+        assert_eq!(path_finder.cost((3, 3)), Some(5));
+        assert_eq!(path_finder.path((2, 2)).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn hex_walls_block_steps_and_sight() {
+        let rows = ["....", "....", "...."];
+        let mut grid = fixtures::level(Some("hex"), &rows, &[("warrior", (1, 1), 1)])
+            .create_grid(&fixtures::info());
+        grid.set_edge((1, 1), 0, Some(EdgeKind::Wall));
+        let unit = grid.unit((1, 1)).unwrap().clone();
+        assert!(!grid.can_step(&unit, (1, 1), (2, 1)));
+        assert!(!grid.can_step(&unit, (2, 1), (1, 1)));
+        assert!(!grid.line_of_sight((0, 1), (3, 1)));
+        assert!(grid.line_of_sight((0, 0), (3, 0)));
+        assert_eq!(grid.path_finder((1, 1)).cost((2, 1)), Some(2));
+    }
+
+    #[test]
+    fn diagonal_steps_cost_extra() {
+        let mut grid = fixtures::grid(&["...", "...", "..."], &[("warrior", (0, 0), 1)]);
+        assert_eq!(grid.path_finder((0, 0)).path((2, 2)).unwrap().len(), 4);
+        grid.set_diagonal_cost(Some(0));
+        let path_finder = grid.path_finder((0, 0));
+        assert_eq!(path_finder.path((2, 2)), Some(vec![(1, 1), (2, 2)]));
+        assert_eq!(path_finder.cost((2, 2)), Some(2));
+        grid.set_diagonal_cost(Some(2));
+        assert_eq!(grid.path_finder((0, 0)).cost((1, 1)), Some(2));
+    }
+
+    #[test]
+    fn diagonal_steps_go_around_one_wall() {
+        let mut grid = fixtures::grid(&["..", ".."], &[("warrior", (0, 0), 1)]);
+        grid.set_diagonal_cost(Some(0));
+        let unit = grid.unit((0, 0)).unwrap().clone();
+        grid.set_edge((0, 0), 0, Some(EdgeKind::Wall));
+        assert!(grid.can_step(&unit, (0, 0), (1, 1)));
+        grid.set_edge((0, 0), 1, Some(EdgeKind::Wall));
+        assert!(!grid.can_step(&unit, (0, 0), (1, 1)));
+    }
+
+    #[test]
+    fn ranged_attacks_measure_by_metric() {
+        let row = ".......";
+        let grid = fixtures::grid(&[row; 7], &[]);
+        let count = |metric| AttackRange::ranged(&grid, (3, 3), 1, 3, metric).count();
+        assert_eq!(count(Metric::Manhattan), 24);
+        assert_eq!(count(Metric::Euclidean), 28);
+        assert_eq!(count(Metric::Chebyshev), 48);
+        let ring = AttackRange::ranged(&grid, (3, 3), 3, 3, Metric::Euclidean)
+            .collect::<Vec<_>>();
+        assert!(ring.contains(&(5, 5)));
+        assert!(!ring.contains(&(6, 5)));
+    }
+
+    #[test]
+    fn wrapping_maps_lead_around() {
+        let mut grid = fixtures::grid(&[".....", "....."], &[("warrior", (0, 0), 1)]);
+        assert_eq!(grid.neighbor((0, 0), 2), None);
+        assert_eq!(grid.path_finder((0, 0)).cost((3, 0)), Some(3));
+
+        grid.set_wrap(Wrap::Horizontal);
+        assert_eq!(grid.neighbor((0, 0), 2), Some((4, 0)));
+        assert_eq!(grid.neighbor((0, 0), 3), None);
+        assert_eq!(grid.distance((0, 0), (4, 1)), 2);
+        let path_finder = grid.path_finder((0, 0));
+        assert_eq!(path_finder.path((3, 0)), Some(vec![(4, 0), (3, 0)]));
+        assert_eq!(grid.route((0, 0), (3, 0)).unwrap().cost, 2);
+
+        grid.set_wrap(Wrap::Both);
+        assert_eq!(grid.neighbor((0, 0), 3), Some((0, 1)));
+    }
+
+    #[test]
+    fn zones_of_control_reach_across_the_seam() {
+        let mut grid = fixtures::grid(&["......"],
+                                      &[("warrior", (0, 0), 1), ("warrior", (3, 0), 2)]);
+        assert_eq!(grid.path_finder((0, 0)).cost((5, 0)), None);
+        grid.set_wrap(Wrap::Horizontal);
+        let path_finder = grid.path_finder((0, 0));
+        assert_eq!(path_finder.cost((4, 0)), Some(2));
+        // Next to the enemy, the warrior has to stop.
+        assert_eq!(path_finder.cost((3, 0)), None);
+        let unit = grid.unit((0, 0)).unwrap().clone();
+        assert!(grid.stops_in(&unit, (2, 0)));
+        assert!(grid.stops_in(&unit, (4, 0)));
+    }
+
+    #[test]
+    fn ranged_attacks_on_small_wrapping_maps() {
+        let mut grid = fixtures::grid(&["...", "...", "..."], &[]);
+        grid.set_wrap(Wrap::Both);
+        // Every other tile is one or two steps away, too close for `min`.
+        let targets = AttackRange::ranged(&grid, (1, 1), 3, 3, Metric::Manhattan)
+            .collect::<Vec<_>>();
+        assert_eq!(targets, vec![]);
+
+        let mut targets = AttackRange::area(&grid, (1, 1), 1, 5, Metric::Manhattan)
+            .collect::<Vec<_>>();
+        targets.sort();
+        assert_eq!(targets,
+                   vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 2), (2, 0), (2, 1), (2, 2)]);
+        let near = AttackRange::area(&grid, (1, 1), 2, 5, Metric::Manhattan).count();
+        assert_eq!(near, 4);
+    }
+
+    #[test]
+    fn climbing_costs_extra_movement() {
+        let mut grid = fixtures::grid(&["...."], &[("warrior", (0, 0), 1)]);
+        grid.tile_mut((1, 0)).elevation = 2;
+        let path_finder = grid.path_finder((0, 0));
+        assert_eq!(path_finder.cost((1, 0)), Some(3));
+        assert_eq!(path_finder.cost((2, 0)), Some(4));
+    }
+
+    #[test]
+    fn high_ground_reaches_further_down() {
+        let mut grid = fixtures::grid(&["......"], &[]);
+        let targets = |grid: &super::Grid| {
+            AttackRange::ranged(grid, (0, 0), 2, 3, Metric::Manhattan).collect::<Vec<_>>()
+        };
+        assert_eq!(targets(&grid), vec![(3, 0), (2, 0)]);
+        grid.tile_mut((0, 0)).elevation = 1;
+        assert_eq!(targets(&grid), vec![(4, 0), (3, 0), (2, 0)]);
+        grid.tile_mut((4, 0)).elevation = 1;
+        assert_eq!(targets(&grid), vec![(3, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn height_changes_damage() {
+        let units = [("archer", (0, 0), 1), ("warrior", (2, 0), 2), ("warrior", (3, 0), 1)];
+        let mut grid = fixtures::grid(&["...."], &units);
+        let shot = grid.calculate_damage((0, 0), (2, 0), 0, None);
+        let stab = grid.calculate_damage((3, 0), (2, 0), 0, None);
+        let strike = grid.calculate_damage((2, 0), (3, 0), 0, None);
+
+        // Shooting down hits harder, but stabbing down does not, and
+        // anything uphill is weaker.
+        grid.tile_mut((0, 0)).elevation = 1;
+        grid.tile_mut((3, 0)).elevation = 2;
+        assert_eq!(grid.calculate_damage((0, 0), (2, 0), 0, None), shot * 110 / 100);
+        assert_eq!(grid.calculate_damage((3, 0), (2, 0), 0, None), stab);
+        assert_eq!(grid.calculate_damage((2, 0), (3, 0), 0, None), strike * 80 / 100);
+    }
+
+    #[test]
+    fn blasts_fall_off_around_the_target() {
+        let grid = fixtures::grid(&[".....", ".....", "....."], &[("catapult", (0, 1), 1)]);
+        let catapult = grid.unit((0, 1)).unwrap();
+        let mut area = grid.blast_area(catapult, 0, (3, 1));
+        area.sort();
+        assert_eq!(area,
+                   vec![((2, 1), 50), ((3, 0), 50), ((3, 1), 100), ((3, 2), 50), ((4, 1), 50)]);
+        let mut area = grid.blast_area(catapult, 0, (4, 2));
+        area.sort();
+        assert_eq!(area, vec![((3, 2), 50), ((4, 1), 50), ((4, 2), 100)]);
+    }
+
+    #[test]
+    fn area_attacks_hit_friends_without_retaliation() {
+        let units = [("catapult", (0, 1), 1), ("archer", (3, 1), 2), ("warrior", (4, 1), 1)];
+        let mut grid = fixtures::grid(&[".....", ".....", "....."], &units);
+        let mut archer = grid.unit((3, 1)).unwrap().clone();
+        let mut warrior = grid.unit((4, 1)).unwrap().clone();
+        let hit = grid.calculate_damage((0, 1), (3, 1), 0, None);
+        let splash = grid.calculate_damage((0, 1), (4, 1), 0, None) * 50 / 100;
+        assert!(splash > 0);
+        archer.receive_damage(hit);
+        warrior.receive_damage(splash);
+
+        grid.attack((0, 1), (3, 1), 0);
+        assert_eq!(grid.unit((3, 1)).unwrap().health, archer.health);
+        assert_eq!(grid.unit((4, 1)).unwrap().health, warrior.health);
+        // The archer could shoot back at this range, but not at a blast.
+        assert_eq!(grid.unit((0, 1)).unwrap().health, 10);
+    }
+
+    #[test]
+    fn spears_pierce_one_enemy() {
+        let units = [("raccoon", (0, 0), 1),
+                     ("warrior", (1, 0), 2),
+                     ("warrior", (2, 0), 2),
+                     ("warrior", (3, 0), 2)];
+        let mut grid = fixtures::grid(&["....."], &units);
+        {
+            let raccoon = grid.unit((0, 0)).unwrap();
+            let reach = AttackRange::spear(&grid, raccoon, 0, (0, 0), 3).collect::<Vec<_>>();
+            assert_eq!(reach, vec![(1, 0), (2, 0)]);
+        }
+
+        let mut first = grid.unit((1, 0)).unwrap().clone();
+        let mut second = grid.unit((2, 0)).unwrap().clone();
+        first.receive_damage(grid.calculate_damage((0, 0), (1, 0), 0, None));
+        second.receive_damage(grid.calculate_damage((0, 0), (2, 0), 0, None) * 50 / 100);
+        grid.attack((0, 0), (2, 0), 0);
+        assert_eq!(grid.unit((1, 0)).unwrap().health, first.health);
+        assert_eq!(grid.unit((2, 0)).unwrap().health, second.health);
+        assert_eq!(grid.unit((3, 0)).unwrap().health, 10);
+        // The second warrior is out of reach of its own sword.
+        assert_eq!(grid.unit((0, 0)).unwrap().health, 10);
+    }
+
+    #[test]
+    fn spears_stop_at_friends_and_walls() {
+        let units = [("raccoon", (0, 0), 1), ("warrior", (1, 0), 1), ("warrior", (2, 0), 2)];
+        let mut grid = fixtures::grid(&["...."], &units);
+        {
+            let raccoon = grid.unit((0, 0)).unwrap();
+            let reach = AttackRange::spear(&grid, raccoon, 0, (0, 0), 3).collect::<Vec<_>>();
+            assert_eq!(reach, vec![(1, 0)]);
+        }
+        grid.remove_unit((1, 0));
+        grid.set_edge((1, 0), 0, Some(EdgeKind::Cliff));
+        let raccoon = grid.unit((0, 0)).unwrap();
+        let reach = AttackRange::spear(&grid, raccoon, 0, (0, 0), 3).collect::<Vec<_>>();
+        assert_eq!(reach, vec![(1, 0)]);
+    }
+
+    #[test]
+    fn weapons_have_their_own_ranges() {
+        let units = [("archer", (0, 0), 1), ("warrior", (1, 0), 2), ("warrior", (2, 0), 2)];
+        let grid = fixtures::grid(&["...."], &units);
+        let archer = grid.unit((0, 0)).unwrap();
+        let with = |weapon, moved| {
+            grid.find_attackable_with(archer, weapon, (0, 0), moved).collect::<Vec<_>>()
+        };
+        assert_eq!(with(0, false), vec![(2, 0)]);
+        assert_eq!(with(1, false), vec![(1, 0)]);
+        assert_eq!(with(0, true), vec![]);
+        assert_eq!(with(1, true), vec![(1, 0)]);
+        let mut any = grid.find_attackable(archer, (0, 0), false).collect::<Vec<_>>();
+        any.sort();
+        assert_eq!(any, vec![(1, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn retaliation_uses_a_weapon_that_reaches() {
+        let units = [("warrior", (0, 0), 1), ("archer", (1, 0), 2), ("archer", (3, 0), 1)];
+        let grid = fixtures::grid(&["...."], &units);
+        assert_eq!(grid.retaliation((0, 0), (1, 0), 0),
+                   Some(grid.calculate_damage((1, 0), (0, 0), 1, Some(0))));
+        assert_eq!(grid.retaliation((3, 0), (1, 0), 0),
+                   Some(grid.calculate_damage((1, 0), (3, 0), 0, Some(0))));
+    }
+
+    #[test]
+    fn ranged_retaliation_can_be_turned_off() {
+        let mut grid = fixtures::grid(&["...."], &[("archer", (0, 0), 1), ("archer", (2, 0), 2)]);
+        assert!(grid.retaliation((0, 0), (2, 0), 0).is_some());
+        grid.combat.ranged_retaliation = false;
+        assert_eq!(grid.retaliation((0, 0), (2, 0), 0), None);
+        grid.attack((0, 0), (2, 0), 0);
+        assert_eq!(grid.unit((0, 0)).unwrap().health, 10);
+    }
+
+    #[test]
+    fn retaliation_is_a_share_of_an_attack() {
+        let mut grid = fixtures::grid(&[".."], &[("warrior", (0, 0), 1), ("warrior", (1, 0), 2)]);
+        let full = grid.retaliation((0, 0), (1, 0), 0).unwrap();
+        assert_eq!(full, grid.calculate_damage((1, 0), (0, 0), 0, None));
+        grid.combat.retaliation = 50;
+        assert_eq!(grid.retaliation((0, 0), (1, 0), 0), Some(full * 50 / 100));
+    }
+
+    #[test]
+    fn first_strike_hits_before_the_attack() {
+        let mut grid = fixtures::grid(&[".."], &[("warrior", (0, 0), 1), ("raccoon", (1, 0), 2)]);
+        let mut after = grid.clone();
+        let strike = grid.calculate_damage((1, 0), (0, 0), 0, Some(0));
+        after.unit_mut((0, 0)).unwrap().receive_damage(strike);
+        let weakened = after.calculate_damage((0, 0), (1, 0), 0, None);
+        after.unit_mut((1, 0)).unwrap().receive_damage(weakened);
+        assert!(weakened < grid.calculate_damage((0, 0), (1, 0), 0, None));
+
+        // The raccoon does not strike back a second time.
+        grid.attack((0, 0), (1, 0), 0);
+        assert_eq!(grid.unit((0, 0)).unwrap().health, after.unit((0, 0)).unwrap().health);
+        assert_eq!(grid.unit((1, 0)).unwrap().health, after.unit((1, 0)).unwrap().health);
+    }
+
+    #[test]
+    fn damage_formula_decides_what_health_counts() {
+        let mut grid = fixtures::grid(&[".."], &[("warrior", (0, 0), 1), ("warrior", (1, 0), 2)]);
+        let full = grid.calculate_damage((0, 0), (1, 0), 0, None);
+        grid.unit_mut((0, 0)).unwrap().health = 5;
+        assert!(grid.calculate_damage((0, 0), (1, 0), 0, None) < full);
+        grid.combat.formula = DamageFormula::Flat;
+        assert_eq!(grid.calculate_damage((0, 0), (1, 0), 0, None), full);
+        grid.unit_mut((1, 0)).unwrap().health = 5;
+        grid.combat.formula = DamageFormula::AttackerHealth;
+        let weakened = grid.calculate_damage((0, 0), (1, 0), 0, None);
+        grid.unit_mut((1, 0)).unwrap().health = 10;
+        assert_eq!(grid.calculate_damage((0, 0), (1, 0), 0, None), weakened);
+    }
+
+    #[test]
+    fn terrain_bonuses_belong_to_unit_kinds() {
+        let units = [("archer", (0, 0), 1), ("catapult", (1, 0), 1), ("warrior", (2, 0), 1)];
+        let grid = fixtures::grid(&["mw.", "www"], &units);
+        let (mountains, woods) = (&grid.tile((0, 0)).terrain, &grid.tile((1, 0)).terrain);
+        let (archer, catapult) = (grid.unit((0, 0)).unwrap(), grid.unit((1, 0)).unwrap());
+        let warrior = grid.unit((2, 0)).unwrap();
+        assert_eq!(archer.attack_bonus(mountains), 20);
+        assert_eq!(archer.attack_bonus(woods), 0);
+        assert_eq!(catapult.attack_bonus(woods), -30);
+        assert_eq!(warrior.attack_bonus(woods), 0);
+        // Woods give 10 and both kinds 20, but the catapult loses 10 there.
+        assert_eq!(warrior.defense_bonus(woods), 30);
+        assert_eq!(catapult.defense_bonus(woods), 10);
+    }
+
+    #[test]
+    fn archers_shoot_harder_from_mountains() {
+        let damage = |rows| {
+            let mut grid = fixtures::grid(rows, &[("archer", (0, 0), 1), ("warrior", (2, 0), 2)]);
+            grid.unit_mut((2, 0)).unwrap().facing = 2;
+            (grid.calculate_damage((0, 0), (2, 0), 0, None), grid)
+        };
+        let (low, _) = damage(&["..."]);
+        let (high, grid) = damage(&["m.."]);
+        assert!(high > low);
+        let archer = grid.unit((0, 0)).unwrap();
+        let warrior = grid.unit((2, 0)).unwrap();
+        let terrain = &grid.tile((0, 0)).terrain;
+        let defense = warrior.defense_bonus(&grid.tile((2, 0)).terrain);
+        assert_eq!(high, archer.attack_damage(0, terrain, warrior, defense, grid.combat.formula));
+    }
+
+    #[test]
+    fn zones_of_control_stop_movement() {
+        let rows = [".....", "....."];
+        let grid = fixtures::grid(&rows, &[("warrior", (0, 0), 1), ("warrior", (2, 1), 2)]);
+        let path_finder = grid.path_finder((0, 0));
+        assert_eq!(path_finder.cost((2, 0)), Some(2));
+        assert_eq!(path_finder.cost((3, 0)), None);
+
+        // Starting next to an enemy does not keep a unit from leaving.
+        let grid = fixtures::grid(&rows, &[("warrior", (2, 0), 1), ("warrior", (2, 1), 2)]);
+        let path_finder = grid.path_finder((2, 0));
+        assert_eq!(path_finder.cost((0, 0)), Some(2));
+        assert_eq!(path_finder.cost((4, 0)), Some(2));
+        assert_eq!(path_finder.cost((0, 1)), Some(3));
+    }
+
+    #[test]
+    fn zones_of_control_depend_on_the_kind() {
+        let rows = [".....", "....."];
+        let units = [("raccoon", (0, 0), 1), ("catapult", (1, 0), 1), ("warrior", (2, 1), 2)];
+        let grid = fixtures::grid(&rows, &units);
+        assert_eq!(grid.path_finder((0, 0)).cost((3, 0)), Some(3));
+
+        let catapult = grid.unit((1, 0)).unwrap();
+        assert_eq!(grid.step_cost(catapult, (1, 0), (2, 0)), 3);
+        assert_eq!(grid.step_cost(catapult, (2, 0), (3, 0)), 1);
+        let path_finder = grid.path_finder((1, 0));
+        assert_eq!(path_finder.cost((2, 0)), Some(3));
+        assert_eq!(path_finder.cost((3, 0)), None);
+    }
+
+    #[test]
+    fn surroundings_change_damage() {
+        let rows = ["...", "...", "..."];
+        let mut grid = fixtures::grid(&rows, &[("warrior", (0, 1), 1), ("warrior", (1, 1), 2)]);
+        let alone = grid.calculate_damage((0, 1), (1, 1), 0, None);
+        assert_eq!(grid.surroundings_modifier((0, 1), (1, 1)), 100);
+
+        let ally = grid.unit((0, 1)).unwrap().clone();
+        grid.add_unit(ally.clone(), (1, 0));
+        assert_eq!(grid.surroundings_modifier((0, 1), (1, 1)), 110);
+        // Opposite the attacker, an ally flanks and pincers at once.
+        grid.add_unit(ally, (2, 1));
+        assert_eq!(grid.surroundings_modifier((0, 1), (1, 1)), 140);
+        let friend = grid.unit((1, 1)).unwrap().clone();
+        grid.add_unit(friend, (1, 2));
+        assert_eq!(grid.surroundings_modifier((0, 1), (1, 1)), 135);
+        assert_eq!(grid.calculate_damage((0, 1), (1, 1), 0, None), alone * 135 / 100);
+
+        // Units further away do not count.
+        let units = [("warrior", (0, 0), 1), ("warrior", (1, 0), 2), ("warrior", (3, 0), 1)];
+        let grid = fixtures::grid(&["...."], &units);
+        assert_eq!(grid.surroundings_modifier((0, 0), (1, 0)), 100);
+    }
+
+    #[test]
+    fn forecasts_count_surroundings() {
+        let rows = ["...", "...", "..."];
+        let mut grid = fixtures::grid(&rows, &[("warrior", (0, 1), 1), ("archer", (1, 1), 2)]);
+        let ally = grid.unit((0, 1)).unwrap().clone();
+        grid.add_unit(ally.clone(), (1, 0));
+        grid.add_unit(ally, (2, 1));
+        let damage = grid.calculate_damage((0, 1), (1, 1), 0, None);
+        let forecast = grid.forecast((0, 1), (1, 1), 0).unwrap();
+        assert_eq!((forecast.min, forecast.max), ((damage + 5) / 10, (damage + 5) / 10));
+
+        grid.remove_unit((1, 0));
+        grid.remove_unit((2, 1));
+        let forecast = grid.forecast((0, 1), (1, 1), 0).unwrap();
+        assert!(forecast.max < (damage + 5) / 10);
+    }
+}
@@ -1,14 +1,19 @@
-use std::collections::BTreeSet;
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+use std::f64;
 
 use glorious::{Color, Renderer, Sprite};
 use sdl2::rect::Rect;
 
+use action::Action;
 use common::{State, Message, ModalBox};
+use edge::EdgeKind;
 use faction::Faction;
-use grid::PathFinder;
+use grid::{PathFinder, Route};
 use menus::ModalMenu;
 use resources::{FIRA_SANS_PATH, MARKER_PATH};
 use target_selector::TargetSelector;
+use topology::Topology;
 use unit::Unit;
 use unit_mover::UnitMover;
 
@@ -20,6 +25,24 @@ const COLOR_BLUE_UNIT_SPENT: Color = Color(65, 120, 140, 0xcc);
 const COLOR_SELECTED: Color = Color(0xdd, 0xee, 0x77, 0xbb);
 const COLOR_MOVEMENT_RANGE: Color = Color(0x00, 0xff, 0xff, 0x77);
 const COLOR_ATTACK_RANGE: Color = Color(0xff, 0x66, 0x66, 0x77);
+const COLOR_ROUTE: Color = Color(0xee, 0xaa, 0x33, 0x77);
+const COLOR_THREAT: Color = Color(0xff, 0x33, 0x33, 0x44);
+const COLOR_TELEPORTER: Color = Color(0x99, 0x44, 0xcc, 0xaa);
+const COLOR_WALL: Color = Color(0x33, 0x33, 0x33, 0xff);
+const COLOR_RIVER: Color = Color(0x33, 0x66, 0xee, 0xff);
+const COLOR_CLIFF: Color = Color(0x88, 0x55, 0x22, 0xff);
+const COLOR_LEDGE: Color = Color(0xbb, 0x99, 0x55, 0xff);
+const COLOR_PATH: Color = Color(0xee, 0xaa, 0x33, 0xdd);
+
+/// The thickness of the lines drawn for edges.
+const EDGE_WIDTH: u32 = 4;
+
+/// The thickness of the movement arrow, and the length of its head.
+const PATH_WIDTH: u32 = 8;
+const ARROW_HEAD: i32 = 10;
+
+/// The size of the mark on the side of a unit it faces.
+const FACING_MARK: u32 = 6;
 
 const COLOR_DEFAULT_EVEN: Color = Color(0xcc, 0xcc, 0xcc, 0xff);
 const COLOR_DEFAULT_ODD: Color = Color(0xdd, 0xdd, 0xdd, 0xff);
@@ -28,6 +51,11 @@ const COLOR_DEFAULT_ODD: Color = Color(0xdd, 0xdd, 0xdd, 0xff);
 struct Selected {
     pos: (u32, u32),
     path_finder: PathFinder,
+    /// The path traced by the cursor, excluding the unit's own tile.
+    path: Vec<(u32, u32)>,
+    /// Whether the cursor picks the destination of a go-to order.
+    ordering: bool,
+    route: Option<Route>,
 }
 
 #[derive(Debug)]
@@ -37,13 +65,64 @@ struct ShowingRangeOf {
     attack_range: BTreeSet<(u32, u32)>,
 }
 
+/// How many enemy units could attack each tile this turn.
+#[derive(Debug)]
+struct Threats {
+    counts: BTreeMap<(u32, u32), u32>,
+}
+
+impl Threats {
+    fn new(state: &State) -> Threats {
+        let faction = state.turn_info.current_faction();
+        let mut counts = BTreeMap::new();
+        let (w, h) = state.grid.size();
+        for y in 0..h {
+            for x in 0..w {
+                match state.grid.unit((x, y)) {
+                    Some(unit) if unit.faction != faction => {}
+                    _ => continue,
+                }
+                let path_finder = state.grid.path_finder((x, y));
+                for pos in path_finder.total_attack_range(&state.grid) {
+                    *counts.entry(pos).or_insert(0) += 1;
+                }
+            }
+        }
+        Threats { counts: counts }
+    }
+}
+
+/// Gives the unit at `pos` a go-to order, or cancels it if `goal` is
+/// `None`, and records it for the turn file.
+pub fn give_order(pos: (u32, u32),
+                  goal: Option<(u32, u32)>,
+                  state: &mut State)
+                  -> Result<(), String> {
+    let action = Action::Order {
+        at: pos,
+        goal: goal,
+    };
+    action.apply(&mut state.grid, &mut state.turn_info)?;
+    state.actions.push(action);
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct GridManager {
     selected: Option<Selected>,
     showing_range_of: Option<ShowingRangeOf>,
+    showing_threats: bool,
+    /// The threats as of the last change to the grid, if worked out yet.
+    threats: Option<Threats>,
     cursor: (u32, u32),
     cursor_hidden: bool,
     mouse: Option<(i32, i32)>,
+    /// The way the moving unit faced before it moved, in case the move is
+    /// cancelled.
+    facing_before_move: usize,
+    /// The go-to order the moving unit gave up by moving, in case the move
+    /// is cancelled.
+    goal_before_move: Option<(u32, u32)>,
 }
 
 impl GridManager {
@@ -52,12 +131,21 @@ impl GridManager {
         GridManager {
             selected: None,
             showing_range_of: None,
+            showing_threats: false,
+            threats: None,
             cursor: cursor,
             cursor_hidden: false,
             mouse: None,
+            facing_before_move: 0,
+            goal_before_move: None,
         }
     }
 
+    #[inline]
+    pub fn cursor(&self) -> (u32, u32) {
+        self.cursor
+    }
+
     #[inline]
     pub fn hide_cursor(&mut self) {
         self.cursor_hidden = false;
@@ -68,28 +156,31 @@ impl GridManager {
         self.selected = None;
     }
 
-    /// Opens the target selection modal for the unit at Cell.
+    /// Forgets what was worked out from the units on the grid, after any
+    /// of them moved, fought or changed sides.
+    #[inline]
+    pub fn grid_changed(&mut self) {
+        self.threats = None;
+    }
+
+    /// Opens the target selection modal for the unit at Cell, attacking
+    /// with the weapon numbered `weapon`.
     /// The origin is used to return to the menu when cancelling.
     pub fn select_target(&mut self,
                          origin: (u32, u32),
                          pos: (u32, u32),
+                         weapon: usize,
                          state: &mut State)
                          -> ModalBox {
         debug!("Selecting target...");
         let targets = {
             let unit = state.grid.unit(pos).expect("no unit to select");
-            if pos == origin {
-                state.grid
-                    .find_attackable_before_moving(unit, pos)
-                    .collect()
-            } else {
-                state.grid
-                    .find_attackable_after_moving(unit, pos)
-                    .collect()
-            }
+            state.grid
+                .find_attackable_with(unit, weapon, pos, pos != origin)
+                .collect()
         };
         self.cursor_hidden = true;
-        Box::new(TargetSelector::new(pos, origin, targets))
+        Box::new(TargetSelector::new(pos, origin, weapon, targets, state))
     }
 
     /// Moves the selected unit from origin to target and opens up the action menu.
@@ -110,29 +201,88 @@ impl GridManager {
         self.move_cursor_to(target, state);
         self.cursor_hidden = true;
 
+        // Taking control of the unit cancels its go-to order.
+        self.goal_before_move = state.grid.unit(origin).and_then(|unit| unit.goal);
+        if self.goal_before_move.is_some() {
+            give_order(origin, None, state).expect("could not cancel go-to order");
+        }
+
         let unit = state.grid.remove_unit(origin);
-        let mut path = selected.path_finder.random_path_rev(target).collect::<Vec<_>>();
-        path.reverse();
+        self.facing_before_move = unit.facing;
+        let path = if selected.path.last() == Some(&target) {
+            selected.path
+        } else {
+            selected.path_finder.path(target).expect("no path to target")
+        };
         Some(Box::new(UnitMover::new(unit, origin, path)))
     }
 
     /// Handles the selection of a unit.
     pub fn select_unit(&mut self, pos: (u32, u32), state: &mut State) {
-        let unit = state.grid.unit(pos).expect("cannot select unit on empty tile");
-        if state.turn_info.can_act(unit) {
+        let can_act = {
+            let unit = state.grid.unit(pos).expect("cannot select unit on empty tile");
+            state.turn_info.can_act(unit)
+        };
+        if can_act {
             debug!("Unit at {:?} selected!", pos);
             let path_finder = state.grid.path_finder(pos);
             self.selected = Some(Selected {
                 pos: pos,
                 path_finder: path_finder,
+                path: Vec::new(),
+                ordering: false,
+                route: None,
             });
         }
     }
 
+    /// Switches between moving the selected unit and ordering it to go
+    /// to a tile further away.
+    pub fn toggle_go_to(&mut self, state: &mut State) {
+        let origin = match self.selected {
+            Some(ref mut selected) => {
+                selected.ordering = !selected.ordering;
+                selected.route = None;
+                if selected.ordering {
+                    return;
+                }
+                selected.pos
+            }
+            None => return,
+        };
+        self.move_cursor_to(origin, state);
+    }
+
+    /// Orders the selected unit to go to the cursor, if it can get there.
+    fn order_selected_unit(&mut self, state: &mut State) {
+        let selected = self.selected.take().expect("no unit was selected");
+        let route = match selected.route {
+            Some(ref route) => route.clone(),
+            None => {
+                // TODO: Beep!
+                self.selected = Some(selected);
+                return;
+            }
+        };
+        if let Err(err) = give_order(selected.pos, Some(self.cursor), state) {
+            // TODO: Beep!
+            info!("Could not give go-to order: {}", err);
+            self.selected = Some(selected);
+            return;
+        }
+        info!("Unit at {:?} ordered to go to {:?}, taking {} turns",
+              selected.pos,
+              self.cursor,
+              route.turns);
+    }
+
     /// Handles a confirm press at the given target tile when a unit is selected.
     pub fn confirm(&mut self, state: &mut State) -> Option<ModalBox> {
         let target = self.cursor;
-        if self.selected.is_some() {
+        if self.selected.as_ref().map_or(false, |s| s.ordering) {
+            self.order_selected_unit(state);
+            None
+        } else if self.selected.is_some() {
             self.move_selected_unit_and_act(target, state)
         } else {
             if state.grid.unit(target).is_some() {
@@ -157,69 +307,48 @@ impl GridManager {
         }
     }
 
+    /// Shows or hides every tile the enemy could attack this turn.
+    #[inline]
+    pub fn toggle_threats(&mut self) {
+        self.showing_threats = !self.showing_threats;
+    }
+
     /// Handles the release of the cancel button.
     pub fn cancel_release(&mut self) {
         self.showing_range_of = None;
     }
 
-    /// Destroys the unit on the given tile.
-    fn destroy_unit(&self, pos: (u32, u32), state: &mut State) {
-        state.grid.remove_unit(pos);
-    }
-
-    fn apply_damage(&self, pos: (u32, u32), damage: f64, state: &mut State) -> bool {
-        if state.grid.unit_mut(pos).expect("no unit to apply damage to").receive_damage(damage) {
-            self.destroy_unit(pos, state);
-            true
-        } else {
-            false
-        }
-    }
-
-    fn calculate_damage(&self,
-                        pos: (u32, u32),
-                        target: (u32, u32),
-                        retaliating_to: Option<f64>,
-                        state: &State)
-                        -> f64 {
-        let attacker = state.grid.unit(pos).expect("no attacking unit");
-        let (defender, tile) = state.grid.unit_and_tile(target);
-        let defender = defender.expect("no unit to attack");
-        match retaliating_to {
-            Some(damage) => attacker.retaliation_damage(damage, defender, &tile.terrain),
-            None => attacker.attack_damage(defender, &tile.terrain),
-        }
-    }
-
-    pub fn target_confirmed(&mut self, pos: (u32, u32), target: (u32, u32), state: &mut State) {
+    pub fn target_confirmed(&mut self,
+                            pos: (u32, u32),
+                            target: (u32, u32),
+                            weapon: usize,
+                            state: &mut State) {
         self.cursor_hidden = false;
-
-        let damage = self.calculate_damage(pos, target, None, state);
-        if self.apply_damage(target, damage, state) {
-            // Destroyed defender cannot retaliate.
-            return;
-        }
-        let in_range = {
-            let attacker = state.grid.unit(target).expect("no retaliating unit");
-            state.grid
-                .attack_range_when_retaliating(attacker, target)
-                .any(|p| p == pos)
-        };
-        if in_range {
-            let damage = self.calculate_damage(pos, target, Some(damage), state);
-            self.apply_damage(pos, damage, state);
-        }
+        state.grid.attack(pos, target, weapon);
+        self.grid_changed();
     }
 
     pub fn move_cursor_to(&mut self, pos: (u32, u32), state: &mut State) {
         assert!(pos.0 < state.grid.size().0 && pos.1 < state.grid.size().1);
-        if let Some(ref selected) = self.selected {
-            // TODO: You can move cursor to friendly unit (no crash, though).
-            // Note, that it is important to be able to move the cursor
-            // *through* friendly units, since certain valid paths
-            // would be impossible to selecte, otherwise.
-            if !selected.path_finder.can_move_to(pos) {
+        let moved = pos != self.cursor;
+        if let Some(ref mut selected) = self.selected {
+            if selected.ordering {
+                if !moved {
+                    return;
+                }
+                selected.route = if pos == selected.pos {
+                    None
+                } else {
+                    state.grid.route(selected.pos, pos)
+                };
+            } else if !selected.path_finder.can_move_to(pos) {
+                // TODO: You can move cursor to friendly unit (no crash, though).
+                // Note, that it is important to be able to move the cursor
+                // *through* friendly units, since certain valid paths
+                // would be impossible to selecte, otherwise.
                 return;
+            } else if moved {
+                trace_path(selected, pos, state);
             }
         }
         self.cursor = pos;
@@ -229,12 +358,10 @@ impl GridManager {
     pub fn move_cursor_relative(&mut self, delta: (i32, i32), state: &mut State) {
         self.mouse = None;
 
-        let (w, h) = state.grid.size();
-        let x = self.cursor.0 as i32 + delta.0;
-        let y = self.cursor.1 as i32 + delta.1;
-
-        if 0 <= x && x < w as i32 && 0 <= y && y < h as i32 {
-            self.move_cursor_to((x as u32, y as u32), state);
+        // Going through level coordinates lets the cursor wrap around.
+        let (x, y) = state.grid.to_level(self.cursor);
+        if let Some(pos) = state.grid.from_level((x + delta.0, y + delta.1)) {
+            self.move_cursor_to(pos, state);
         }
     }
 
@@ -253,55 +380,67 @@ impl GridManager {
                              state: &mut State)
                              -> ModalBox {
         debug!("Moved unit from {:?} to {:?}", origin, target);
-
-        let options = {
-            let (unit, tile) = state.grid.unit_and_tile(target);
-            let unit = unit.expect("unreachable; failed to move unit");
-
-            let mut options = Vec::with_capacity(2);
-            let mut find_attackable = if origin == target {
-                state.grid.find_attackable_before_moving(unit, target)
-            } else {
-                state.grid.find_attackable_after_moving(unit, target)
-            };
-            if find_attackable.next().is_some() {
-                options.push("Attack");
+        self.grid_changed();
+
+        // Units with several weapons get to choose one.
+        let (options, weapons) = {
+            let unit = state.grid.unit(target).expect("unreachable; failed to move unit");
+            let moved = origin != target;
+
+            let grid = &state.grid;
+            let mut weapons = Vec::new();
+            if grid.find_attackable(unit, target, moved).next().is_some() {
+                for (w, weapon) in unit.kind.weapons.iter().enumerate() {
+                    if unit.kind.weapons.len() == 1 {
+                        weapons.push(("Attack".to_owned(), w));
+                    } else if grid.find_attackable_with(unit, w, target, moved).next().is_some() {
+                        weapons.push((format!("Attack: {}", weapon.name), w));
+                    }
+                }
             }
-            if unit.kind.capture > 0 && tile.can_be_captured() &&
-               tile.faction != Some(unit.faction) {
-                options.push("Capture");
+
+            let mut options = weapons.iter().map(|w| w.0.clone()).collect::<Vec<_>>();
+            if state.grid.can_capture(target) {
+                options.push("Capture".to_owned());
             }
-            options.push("Wait");
-            options
+            options.push("Turn".to_owned());
+            options.push("Wait".to_owned());
+            (options, weapons)
         };
 
         // Clicking the unit confirms too :)
         let extra_confirm_areas = vec![state.tile_rect(target)];
 
-        let menu = ModalMenu::new(options.iter().map(|&s| s.to_owned()),
+        let menu = ModalMenu::new(options.into_iter(),
                                   0,
                                   (50, 50),
                                   state.resources.font(FIRA_SANS_PATH, 16),
                                   state,
                                   extra_confirm_areas,
                                   move |option, state, queue| {
+            let weapon = option.and_then(|o| weapons.iter().find(|w| w.0 == o).map(|w| w.1));
+            if let Some(weapon) = weapon {
+                debug!("Attack!");
+                state.pop_modal(queue);
+                queue.push(Message::AttackSelected(origin, target, weapon));
+                return;
+            }
             match option {
-                Some("Attack") => {
-                    debug!("Attack!");
-                    state.pop_modal(queue);
-                    queue.push(Message::AttackSelected(origin, target));
-                }
                 Some("Capture") => {
                     debug!("Capture!");
                     state.pop_modal(queue);
-                    queue.push(Message::CaptureSelected(target));
+                    queue.push(Message::CaptureSelected(origin, target));
+                }
+                Some("Turn") => {
+                    state.pop_modal(queue);
+                    queue.push(Message::TurnSelected(origin, target));
                 }
                 Some("Wait") => {
                     debug!("Wait!");
                     state.pop_modal(queue);
                     // TODO: Marking the unit now is one frame quicker.
                     queue.push(Message::UnitSpent(target));
-                    queue.push(Message::WaitSelected);
+                    queue.push(Message::WaitSelected(origin, target));
                 }
                 None => {
                     debug!("Cancel!");
@@ -315,20 +454,55 @@ impl GridManager {
         Box::new(menu)
     }
 
-    pub fn capture_at(&mut self, pos: (u32, u32), state: &mut State) {
-        self.cursor_hidden = false;
-        {
-            let (unit, tile) = state.grid.unit_and_tile_mut(pos);
-            let unit = unit.expect("no unit to capture with");
-            assert!(tile.can_be_captured() && tile.faction != Some(unit.faction));
-
-            let capture = unit.kind.capture * unit.health / 10;
-            info!("Trying to capture {:?} with strength {:?}", tile.terrain.name, capture);
-            if tile.capture(unit.faction, capture) {
-                info!("Tile at {:?} captured by {:?}!", pos, unit.faction);
+    /// Opens a menu for choosing which way the unit that moved from
+    /// `origin` to `pos` faces, and goes back to its action menu after.
+    pub fn select_facing(&mut self,
+                         origin: (u32, u32),
+                         pos: (u32, u32),
+                         state: &mut State)
+                         -> ModalBox {
+        let topology = state.grid.topology();
+        let facing = state.grid.unit(pos).expect("no unit to turn").facing;
+        let names = (0..topology.direction_count())
+            .map(|dir| topology.direction_name(dir))
+            .collect::<Vec<_>>();
+        let options = names.iter().map(|&name| name.to_owned()).collect::<Vec<_>>();
+
+        let menu = ModalMenu::new(options,
+                                  facing,
+                                  (50, 50),
+                                  state.resources.font(FIRA_SANS_PATH, 16),
+                                  state,
+                                  vec![state.tile_rect(pos)],
+                                  move |option, state, queue| {
+            if let Some(dir) = option.and_then(|o| names.iter().position(|&name| name == o)) {
+                state.grid.unit_mut(pos).expect("unit vanished while turning").facing = dir;
+            }
+            state.pop_modal(queue);
+            queue.push(Message::FacingSelected(origin, pos));
+        })
+            .expect("could not create menu");
+        Box::new(menu)
+    }
+
+    /// Moves the unit at `pos` back to `origin`, as it was before moving.
+    pub fn cancel_move(&mut self, origin: (u32, u32), pos: (u32, u32), state: &mut State) {
+        state.grid.move_unit(pos, origin);
+        state.grid.unit_mut(origin).expect("unit vanished while moving back").facing =
+            self.facing_before_move;
+        if let Some(goal) = self.goal_before_move.take() {
+            if let Err(err) = give_order(origin, Some(goal), state) {
+                info!("Could not give back go-to order: {}", err);
             }
         }
+        self.grid_changed();
+    }
+
+    pub fn capture_at(&mut self, pos: (u32, u32), state: &mut State) {
+        self.cursor_hidden = false;
+        state.grid.capture(pos);
         self.unit_spent(pos, state);
+        self.grid_changed();
     }
 
     pub fn unit_spent(&mut self, pos: (u32, u32), state: &mut State) {
@@ -343,6 +517,19 @@ impl GridManager {
         if let Some(pos) = self.mouse.and_then(|(x, y)| state.window_to_grid(x, y)) {
             self.move_cursor_to(pos, state);
         }
+
+        if self.showing_threats && self.threats.is_none() &&
+           !state.turn_info.factions().is_empty() {
+            self.threats = Some(Threats::new(state));
+        }
+    }
+
+    #[inline]
+    fn threat_count(&self, pos: (u32, u32)) -> Option<u32> {
+        if !self.showing_threats {
+            return None;
+        }
+        self.threats.as_ref().and_then(|t| t.counts.get(&pos).cloned())
     }
 
     /// Renders the object.
@@ -376,11 +563,27 @@ impl GridManager {
                     renderer.fill_rect(rect).unwrap();
                 }
 
+                if state.grid.teleporter(pos).is_some() {
+                    let inset = rect.width() as i32 / 4;
+                    renderer.set_draw_color(COLOR_TELEPORTER);
+                    renderer.fill_rect(Rect::new(rect.x() + inset,
+                                                 rect.y() + inset,
+                                                 rect.width() / 2,
+                                                 rect.height() / 2))
+                        .unwrap();
+                }
+
                 let color = self.selected
                     .as_ref()
                     .and_then(|s| {
                         if s.pos == pos {
                             Some(COLOR_SELECTED)
+                        } else if s.ordering {
+                            if s.route.as_ref().map_or(false, |r| r.path.contains(&pos)) {
+                                Some(COLOR_ROUTE)
+                            } else {
+                                None
+                            }
                         } else if s.path_finder.can_move_to(pos) {
                             if unit.is_none() {
                                 Some(COLOR_MOVEMENT_RANGE)
@@ -407,6 +610,12 @@ impl GridManager {
                     }
                 }
 
+                if let Some(count) = self.threat_count(pos) {
+                    renderer.set_draw_color(COLOR_THREAT);
+                    renderer.fill_rect(rect).unwrap();
+                    state.health_label(count).render(renderer, rect.x() + 3, rect.y());
+                }
+
                 if let Some(ref sro) = self.showing_range_of {
                     if sro.pos != pos && sro.attack_range.contains(&pos) {
                         renderer.set_draw_color(COLOR_ATTACK_RANGE);
@@ -419,6 +628,142 @@ impl GridManager {
                 }
             }
         }
+
+        for (pos, dir, kind) in state.grid.edges() {
+            render_edge(pos, dir, kind, state, renderer);
+        }
+
+        if let Some(ref selected) = self.selected {
+            if !selected.ordering {
+                render_path(selected.pos, &selected.path, state, renderer);
+            }
+        }
+    }
+}
+
+/// Extends the path traced by the cursor to `pos`, or falls back to the
+/// shortest path if the unit cannot afford the detour.
+fn trace_path(selected: &mut Selected, pos: (u32, u32), state: &State) {
+    if pos == selected.pos {
+        selected.path.clear();
+        return;
+    }
+    if let Some(i) = selected.path.iter().position(|&p| p == pos) {
+        // Retracing our steps.
+        selected.path.truncate(i + 1);
+        return;
+    }
+
+    let unit = state.grid.unit(selected.pos).expect("selected unit vanished");
+    let last = *selected.path.last().unwrap_or(&selected.pos);
+    let mut prev = selected.pos;
+    let mut cost = 0u32;
+    for &p in selected.path.iter().chain(Some(&pos)) {
+        cost = cost.saturating_add(state.grid.step_cost(unit, prev, p));
+        prev = p;
+    }
+
+    let can_go_on = last == selected.pos || !state.grid.stops_in(unit, last);
+    if can_go_on && state.grid.can_step(unit, last, pos) && cost <= unit.kind.movement.movement {
+        selected.path.push(pos);
+    } else {
+        selected.path = selected.path_finder.path(pos).unwrap_or_else(Vec::new);
+    }
+}
+
+#[inline]
+fn center(rect: Rect) -> (i32, i32) {
+    (rect.x() + rect.width() as i32 / 2, rect.y() + rect.height() as i32 / 2)
+}
+
+/// Draws an edge as a line along the side of the tile facing `dir`.
+fn render_edge(pos: (u32, u32),
+               dir: usize,
+               kind: EdgeKind,
+               state: &State,
+               renderer: &mut Renderer) {
+    let rect = state.tile_rect(pos);
+    let (x, y, w, h) = (rect.x(), rect.y(), rect.width(), rect.height());
+    let t = EDGE_WIDTH;
+    let (hw, right) = (w / 2, x + (w / 2) as i32);
+    // The grid's y axis points up the screen. Hex tiles are drawn like
+    // bricks, so their diagonal sides are halves of the top and bottom.
+    let line = match (state.grid.topology(), dir) {
+        (Topology::Square, 0) |
+        (Topology::Hex, 0) => Rect::new(x + (w - t) as i32, y, t, h),
+        (Topology::Square, 1) => Rect::new(x, y, w, t),
+        (Topology::Square, 2) |
+        (Topology::Hex, 3) => Rect::new(x, y, t, h),
+        (Topology::Square, _) => Rect::new(x, y + (h - t) as i32, w, t),
+        (Topology::Hex, 1) => Rect::new(right, y, hw, t),
+        (Topology::Hex, 2) => Rect::new(x, y, hw, t),
+        (Topology::Hex, 4) => Rect::new(x, y + (h - t) as i32, hw, t),
+        (Topology::Hex, _) => Rect::new(right, y + (h - t) as i32, hw, t),
+    };
+    let color = match kind {
+        EdgeKind::Wall => COLOR_WALL,
+        EdgeKind::River => COLOR_RIVER,
+        EdgeKind::Cliff => COLOR_CLIFF,
+        EdgeKind::Ledge => COLOR_LEDGE,
+    };
+    renderer.set_draw_color(color);
+    renderer.fill_rect(line).unwrap();
+}
+
+/// Draws the path a unit is about to take as an arrow.
+fn render_path(origin: (u32, u32), path: &[(u32, u32)], state: &State, renderer: &mut Renderer) {
+    let last = match path.last() {
+        Some(&last) => last,
+        None => return,
+    };
+    renderer.set_draw_color(COLOR_PATH);
+
+    let half = PATH_WIDTH as i32 / 2;
+    let mut dir = (0, 0);
+    let mut prev_pos = origin;
+    for &pos in path {
+        // Both ends are drawn next to each other, even across the edge of
+        // a wrapping map.
+        let prev = center(state.tile_rect(prev_pos));
+        let next = center(state.tile_rect_from(prev_pos, pos));
+        let adjacent = state.grid.is_step(prev_pos, pos);
+        prev_pos = pos;
+        if !adjacent {
+            // Don't draw across the map for a teleporter.
+            continue;
+        }
+        dir = ((next.0 - prev.0).signum(), (next.1 - prev.1).signum());
+        if dir.0 != 0 && dir.1 != 0 {
+            // A slanted step, drawn as a run of squares.
+            let len = cmp::max((next.0 - prev.0).abs(), (next.1 - prev.1).abs());
+            let count = cmp::max(len / half, 1);
+            for i in 0..count + 1 {
+                let x = prev.0 + (next.0 - prev.0) * i / count;
+                let y = prev.1 + (next.1 - prev.1) * i / count;
+                renderer.fill_rect(Rect::new(x - half, y - half, PATH_WIDTH, PATH_WIDTH)).unwrap();
+            }
+            continue;
+        }
+        let (x0, x1) = (cmp::min(prev.0, next.0), cmp::max(prev.0, next.0));
+        let (y0, y1) = (cmp::min(prev.1, next.1), cmp::max(prev.1, next.1));
+        let rect = Rect::new(x0 - half,
+                             y0 - half,
+                             (x1 - x0) as u32 + PATH_WIDTH,
+                             (y1 - y0) as u32 + PATH_WIDTH);
+        renderer.fill_rect(rect).unwrap();
+    }
+
+    // The head: ever narrower strips across the direction of travel.
+    let (cx, cy) = center(state.tile_rect(last));
+    for i in 0..ARROW_HEAD {
+        let along = (cx + dir.0 * (half + i), cy + dir.1 * (half + i));
+        let across = 2 * (ARROW_HEAD - i);
+        let rect = if dir.0 != 0 {
+            Rect::new(along.0, along.1 - across / 2, 1, across as u32)
+        } else {
+            Rect::new(along.0 - across / 2, along.1, across as u32, 1)
+        };
+        renderer.fill_rect(rect).unwrap();
     }
 }
 
@@ -448,6 +793,15 @@ pub fn render_unit(unit: &Unit, rect: Rect, _bg: bool, state: &State, renderer:
     renderer.set_draw_color(color);
     renderer.fill_rect(box_rect).unwrap();
 
+    // Mark the side the unit faces, counting counter-clockwise from east.
+    let count = state.grid.topology().direction_count();
+    let angle = unit.facing as f64 * 2.0 * f64::consts::PI / count as f64;
+    let reach = (cmp::min(hw, hh) - FACING_MARK) as f64;
+    let (cx, cy) = center(rect);
+    let mx = cx + (angle.cos() * reach) as i32 - FACING_MARK as i32 / 2;
+    let my = cy - (angle.sin() * reach) as i32 - FACING_MARK as i32 / 2;
+    renderer.fill_rect(Rect::new(mx, my, FACING_MARK, FACING_MARK)).unwrap();
+
     let (lw, _) = label.size();
 
     let lx = box_rect.x() + (box_rect.width() as i32 - lw as i32) / 2;
@@ -3,32 +3,85 @@ use std::rc::Rc;
 
 use spec::*;
 
+use topology::Metric;
+
+/// Converts a fraction from the info file to whole percent.
+#[inline]
+fn to_percent(fraction: f64) -> i32 {
+    (fraction * 100.0).round() as i32
+}
+
+/// Converts health points from the info file to tenths of a point.
+#[inline]
+fn to_tenths(points: f64) -> u32 {
+    (points * 10.0).round() as u32
+}
+
 #[derive(Debug, Clone)]
 pub enum RangeKind {
     Melee,
     Ranged {
         min: u32,
         max: u32,
+        metric: Metric,
     },
     Spear {
         range: u32,
+        pierce: PierceInfo,
+    },
+    /// Hits every unit within `radius` of a tile in range, without needing
+    /// a line of sight. Damage drops by `falloff` percent per tile from the
+    /// center.
+    Area {
+        min: u32,
+        max: u32,
+        radius: u32,
+        metric: Metric,
+        falloff: u32,
+        friendly_fire: bool,
     },
 }
 
 impl RangeKind {
     fn from_spec(spec: RangeSpec) -> Result<RangeKind, String> {
+        let metric = match spec.metric {
+            Some(ref name) => {
+                Metric::from_name(name).ok_or_else(|| format!("unrecognized metric {:?}", name))?
+            }
+            None => Metric::Manhattan,
+        };
         Ok(match &spec.kind[..] {
             "melee" => RangeKind::Melee,
             "ranged" => {
                 RangeKind::Ranged {
                     min: spec.min.ok_or_else(|| "missing field 'min' for ranged range".to_owned())?,
                     max: spec.max.ok_or_else(|| "missing field 'max' for ranged range".to_owned())?,
+                    metric: metric,
                 }
             }
             "spear" => {
                 RangeKind::Spear {
                     range: spec.range
                         .ok_or_else(|| "missing field 'range' for spear range".to_owned())?,
+                    pierce: match spec.pierce {
+                        Some(spec) => PierceInfo::from_spec(spec)?,
+                        None => PierceInfo::default(),
+                    },
+                }
+            }
+            "area" => {
+                let falloff = spec.falloff.unwrap_or(0.0);
+                if falloff < 0.0 {
+                    return Err("'falloff' for area range cannot be negative".to_owned());
+                }
+                RangeKind::Area {
+                    min: spec.min.ok_or_else(|| "missing field 'min' for area range".to_owned())?,
+                    max: spec.max.ok_or_else(|| "missing field 'max' for area range".to_owned())?,
+                    radius: spec.radius
+                        .ok_or_else(|| "missing field 'radius' for area range".to_owned())?,
+                    metric: metric,
+                    falloff: to_percent(falloff) as u32,
+                    friendly_fire: spec.friendly_fire.unwrap_or(false),
                 }
             }
             kind => return Err(format!("unrecognized range kind {:?}", kind)),
@@ -36,6 +89,33 @@ impl RangeKind {
     }
 }
 
+/// Which units a spear passes through on its way to the target.
+#[derive(Clone, Debug, Default)]
+pub struct PierceInfo {
+    /// How many enemies it passes through.
+    pub enemies: u32,
+    /// Whether it passes through friendly units, unharmed.
+    pub friendlies: bool,
+    /// How much less damage each enemy takes than the one before it, in
+    /// percent of the full damage.
+    pub falloff: u32,
+}
+
+impl PierceInfo {
+    #[inline]
+    fn from_spec(spec: PierceSpec) -> Result<PierceInfo, String> {
+        let falloff = spec.falloff.unwrap_or(0.0);
+        if falloff < 0.0 {
+            return Err("'falloff' for piercing cannot be negative".to_owned());
+        }
+        Ok(PierceInfo {
+            enemies: spec.enemies.unwrap_or(0),
+            friendlies: spec.friendlies.unwrap_or(false),
+            falloff: to_percent(falloff) as u32,
+        })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SpriteInfo {
     pub texture: String,
@@ -55,7 +135,8 @@ impl SpriteInfo {
 #[derive(Clone, Debug)]
 pub struct TerrainInfo {
     pub name: String,
-    pub defense: f64,
+    /// In percent.
+    pub defense: i32,
     pub sprite: Option<SpriteInfo>,
     pub capture: u32,
 }
@@ -69,7 +150,7 @@ impl TerrainInfo {
         };
         Ok(TerrainInfo {
             name: name,
-            defense: spec.defense,
+            defense: to_percent(spec.defense),
             sprite: sprite,
             capture: spec.capture.unwrap_or(0),
         })
@@ -80,25 +161,39 @@ pub type Terrain = Rc<TerrainInfo>;
 
 #[derive(Clone, Debug)]
 pub struct AttackInfo {
-    pub damage: f64,
+    pub name: String,
+    /// In tenths of a health point.
+    pub damage: u32,
     pub range: RangeKind,
-    pub modifiers: HashMap<String, f64>,
+    /// Percentages of the damage dealt to each defense class.
+    pub modifiers: HashMap<String, u32>,
 }
 
 impl AttackInfo {
     #[inline]
-    fn from_spec(spec: AttackSpec) -> Result<AttackInfo, String> {
+    fn from_spec(spec: AttackSpec, default_name: String) -> Result<AttackInfo, String> {
+        if spec.damage < 0.0 {
+            return Err("'damage' cannot be negative".to_owned());
+        }
+        if spec.modifiers.values().any(|&m| m < 0.0) {
+            return Err("damage modifiers cannot be negative".to_owned());
+        }
         Ok(AttackInfo {
-            damage: spec.damage,
+            name: spec.name.unwrap_or(default_name),
+            damage: to_tenths(spec.damage),
             range: RangeKind::from_spec(spec.range)?,
-            modifiers: spec.modifiers,
+            modifiers: spec.modifiers
+                .into_iter()
+                .map(|(class, modifier)| (class, to_percent(modifier) as u32))
+                .collect(),
         })
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct DefenseInfo {
-    pub defense: f64,
+    /// In percent.
+    pub defense: i32,
     pub class: String,
 }
 
@@ -106,7 +201,7 @@ impl DefenseInfo {
     #[inline]
     fn from_spec(spec: DefenseSpec) -> Result<DefenseInfo, String> {
         Ok(DefenseInfo {
-            defense: spec.defense,
+            defense: to_percent(spec.defense),
             class: spec.class,
         })
     }
@@ -149,13 +244,63 @@ impl MovementInfo {
     }
 }
 
+/// How much better or worse a unit kind fights on some terrain.
+#[derive(Clone, Debug)]
+pub struct TerrainBonusInfo {
+    /// Extra damage dealt from the terrain, in percent.
+    pub attack: i32,
+    /// Extra defense on the terrain, in percent.
+    pub defense: i32,
+}
+
+impl TerrainBonusInfo {
+    #[inline]
+    fn from_spec(spec: TerrainBonusSpec) -> Result<TerrainBonusInfo, String> {
+        Ok(TerrainBonusInfo {
+            attack: to_percent(spec.attack.unwrap_or(0.0)),
+            defense: to_percent(spec.defense.unwrap_or(0.0)),
+        })
+    }
+}
+
+/// How enemies next to a tile hinder a unit moving through it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZoneOfControl {
+    /// Enemies make no difference.
+    Ignore,
+    /// The unit has to stop on entering the tile.
+    Stop,
+    /// Entering the tile costs this much more.
+    Cost(u32),
+}
+
+impl ZoneOfControl {
+    fn from_spec(spec: ZoneOfControlSpec) -> Result<ZoneOfControl, String> {
+        Ok(match &spec.kind[..] {
+            "ignore" => ZoneOfControl::Ignore,
+            "stop" => ZoneOfControl::Stop,
+            "cost" => {
+                ZoneOfControl::Cost(spec.cost
+                    .ok_or_else(|| "missing field 'cost' for zone of control".to_owned())?)
+            }
+            kind => return Err(format!("unrecognized zone of control {:?}", kind)),
+        })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct UnitKindInfo {
     pub name: String,
-    pub attack: AttackInfo,
+    /// Every way the unit can attack; there is always at least one.
+    pub weapons: Vec<AttackInfo>,
     pub defense: DefenseInfo,
     pub movement: MovementInfo,
     pub capture: u32,
+    /// Whether the unit strikes back before an attack on it lands.
+    pub first_strike: bool,
+    /// Bonuses on particular terrain, by its name.
+    pub terrain_bonuses: HashMap<String, TerrainBonusInfo>,
+    pub zone_of_control: ZoneOfControl,
     pub sprite: SpriteInfo,
 }
 
@@ -167,12 +312,39 @@ impl UnitKindInfo {
                     -> Result<UnitKindInfo, String>
         where F: FnMut(&str) -> Option<MovementClass>
     {
+        let weapons = match (spec.attack, spec.weapons) {
+            (Some(attack), None) => vec![attack],
+            (None, Some(weapons)) => weapons,
+            (Some(_), Some(_)) => {
+                return Err(format!("unit kind {:?} has both 'attack' and 'weapons'", name))
+            }
+            (None, None) => return Err(format!("unit kind {:?} has no 'attack'", name)),
+        };
+        if weapons.is_empty() {
+            return Err(format!("unit kind {:?} has no weapons", name));
+        }
+        let weapons = weapons.into_iter()
+            .enumerate()
+            .map(|(i, spec)| AttackInfo::from_spec(spec, format!("weapon {}", i + 1)))
+            .collect::<Result<Vec<_>, String>>()?;
+        let terrain_bonuses = spec.terrain
+            .unwrap_or_else(HashMap::new)
+            .into_iter()
+            .map(|(terrain, spec)| Ok((terrain, TerrainBonusInfo::from_spec(spec)?)))
+            .collect::<Result<HashMap<_, _>, String>>()?;
+
         Ok(UnitKindInfo {
             name: name,
-            attack: AttackInfo::from_spec(spec.attack)?,
+            weapons: weapons,
             defense: DefenseInfo::from_spec(spec.defense)?,
             movement: MovementInfo::from_spec(spec.movement, to_movement_class)?,
             capture: spec.capture.unwrap_or(0),
+            first_strike: spec.first_strike.unwrap_or(false),
+            terrain_bonuses: terrain_bonuses,
+            zone_of_control: match spec.zone_of_control {
+                Some(spec) => ZoneOfControl::from_spec(spec)?,
+                None => ZoneOfControl::Ignore,
+            },
             sprite: SpriteInfo::from_spec(spec.sprite)?,
         })
     }
@@ -180,12 +352,149 @@ impl UnitKindInfo {
 
 pub type UnitKind = Rc<UnitKindInfo>;
 
+/// How the height of tiles affects units, per level of difference.
+#[derive(Clone, Debug, Default)]
+pub struct ElevationInfo {
+    /// Extra maximum range of ranged attacks on lower targets.
+    pub range: u32,
+    /// Extra damage of ranged attacks on lower targets, in percent.
+    pub attack: u32,
+    /// Less damage of attacks on higher targets, in percent.
+    pub uphill: u32,
+    /// Extra movement cost of stepping up.
+    pub climb: u32,
+}
+
+impl ElevationInfo {
+    #[inline]
+    fn from_spec(spec: ElevationSpec) -> Result<ElevationInfo, String> {
+        let attack = spec.attack.unwrap_or(0.0);
+        let uphill = spec.uphill.unwrap_or(0.0);
+        if attack < 0.0 || uphill < 0.0 {
+            return Err("elevation bonuses cannot be negative".to_owned());
+        }
+        Ok(ElevationInfo {
+            range: spec.range.unwrap_or(0),
+            attack: to_percent(attack) as u32,
+            uphill: to_percent(uphill) as u32,
+            climb: spec.climb.unwrap_or(0),
+        })
+    }
+}
+
+/// How the damage of an attack depends on the health of the units.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DamageFormula {
+    /// Damage scales with the attacker's health, and defense with the
+    /// defender's.
+    Standard,
+    /// Only the attacker's health matters; defense always counts in full.
+    AttackerHealth,
+    /// Health does not matter at all.
+    Flat,
+}
+
+impl DamageFormula {
+    #[inline]
+    pub fn from_name(name: &str) -> Option<DamageFormula> {
+        match name {
+            "standard" => Some(DamageFormula::Standard),
+            "attacker_health" => Some(DamageFormula::AttackerHealth),
+            "flat" => Some(DamageFormula::Flat),
+            _ => None,
+        }
+    }
+}
+
+/// How damage is worked out and when units strike back.
+#[derive(Clone, Debug)]
+pub struct CombatInfo {
+    pub formula: DamageFormula,
+    /// Whether units can retaliate with ranged weapons.
+    pub ranged_retaliation: bool,
+    /// Whether retaliation is weakened by the damage just taken, or dealt
+    /// as if the unit was still unhurt.
+    pub scale_retaliation: bool,
+    /// The damage of retaliation, in percent of a normal attack.
+    pub retaliation: u32,
+    /// Extra damage for each other attacking unit next to the defender,
+    /// in percent.
+    pub flanking: u32,
+    /// Extra damage when another attacking unit stands on the opposite
+    /// side of the defender, in percent.
+    pub pincer: u32,
+    /// Less damage for each friendly unit next to the defender, in percent.
+    pub support: u32,
+    /// How much of its defense a unit loses when attacked from the side,
+    /// in percent.
+    pub side_penalty: u32,
+    /// How much of its defense a unit loses when attacked from behind, in
+    /// percent.
+    pub rear_penalty: u32,
+}
+
+impl Default for CombatInfo {
+    fn default() -> CombatInfo {
+        CombatInfo {
+            formula: DamageFormula::Standard,
+            ranged_retaliation: true,
+            scale_retaliation: true,
+            retaliation: 100,
+            flanking: 0,
+            pincer: 0,
+            support: 0,
+            side_penalty: 0,
+            rear_penalty: 0,
+        }
+    }
+}
+
+impl CombatInfo {
+    #[inline]
+    fn from_spec(spec: CombatSpec) -> Result<CombatInfo, String> {
+        let formula = match spec.formula {
+            Some(ref name) => {
+                DamageFormula::from_name(name)
+                    .ok_or_else(|| format!("unrecognized damage formula {:?}", name))?
+            }
+            None => DamageFormula::Standard,
+        };
+        let retaliation = spec.retaliation.unwrap_or(1.0);
+        let flanking = spec.flanking.unwrap_or(0.0);
+        let pincer = spec.pincer.unwrap_or(0.0);
+        let support = spec.support.unwrap_or(0.0);
+        if retaliation < 0.0 || flanking < 0.0 || pincer < 0.0 || support < 0.0 {
+            return Err("combat modifiers cannot be negative".to_owned());
+        }
+        let side_penalty = spec.side_penalty.unwrap_or(0.0);
+        let rear_penalty = spec.rear_penalty.unwrap_or(0.0);
+        for &penalty in &[side_penalty, rear_penalty] {
+            if penalty < 0.0 || penalty > 1.0 {
+                return Err("facing penalties must be between 0 and 1".to_owned());
+            }
+        }
+        Ok(CombatInfo {
+            formula: formula,
+            ranged_retaliation: spec.ranged_retaliation.unwrap_or(true),
+            scale_retaliation: spec.scale_retaliation.unwrap_or(true),
+            retaliation: to_percent(retaliation) as u32,
+            flanking: to_percent(flanking) as u32,
+            pincer: to_percent(pincer) as u32,
+            support: to_percent(support) as u32,
+            side_penalty: to_percent(side_penalty) as u32,
+            rear_penalty: to_percent(rear_penalty) as u32,
+        })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct GameInfo {
     pub movement_classes: HashMap<String, MovementClass>,
     pub unit_kinds: HashMap<String, UnitKind>,
     pub terrain: HashMap<String, Terrain>,
     pub defense_classes: HashSet<String>,
+    pub elevation: ElevationInfo,
+    pub combat: CombatInfo,
 }
 
 impl GameInfo {
@@ -230,15 +539,36 @@ impl GameInfo {
                                                    name.clone(),
                                                    |m| movement_classes.get(m).cloned())
                     ?;
+
+                for tname in info.terrain_bonuses.keys() {
+                    if !terrain.contains_key(&tname[..]) {
+                        return Err(format!("unrecognized terrain {:?} for unit kind {:?}",
+                                           tname,
+                                           name));
+                    }
+                }
+
                 Ok((name, UnitKind::new(info)))
             })
             .collect::<Result<HashMap<_, _>, String>>()?;
 
+        let elevation = match spec.elevation {
+            Some(spec) => ElevationInfo::from_spec(spec)?,
+            None => ElevationInfo::default(),
+        };
+
+        let combat = match spec.combat {
+            Some(spec) => CombatInfo::from_spec(spec)?,
+            None => CombatInfo::default(),
+        };
+
         Ok(GameInfo {
             movement_classes: movement_classes,
             unit_kinds: unit_kinds,
             terrain: terrain,
             defense_classes: spec.defense_classes,
+            elevation: elevation,
+            combat: combat,
         })
     }
 }
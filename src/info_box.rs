@@ -7,11 +7,31 @@ use sdl2_ttf::Font;
 
 use common::State;
 use faction::Faction;
+use resources::FIRA_SANS_PATH;
 
 const BG_COLOR: Color = Color(0x00, 0x00, 0x00, 0x77);
 const TEXT_COLOR: Color = Color(0xff, 0xff, 0xff, 0xff);
 const POS: (i32, i32) = (774, 50);
 const SIZE: (u32, u32) = (200, 50);
+/// The gap between the turn info and the unit info below it.
+const UNIT_GAP: u32 = 10;
+
+/// Describes the unit at `pos`, if any, and how it fares on its tile.
+fn describe_unit(state: &State, pos: (u32, u32)) -> Vec<String> {
+    let (unit, tile) = state.grid.unit_and_tile(pos);
+    let unit = match unit {
+        Some(unit) => unit,
+        None => return Vec::new(),
+    };
+    let terrain = &tile.terrain;
+    let mut lines = vec![format!("{} ({}/10)", unit.kind.name, unit.health),
+                         format!("Defense in {}: {}%", terrain.name, unit.defense_bonus(terrain))];
+    let attack = unit.attack_bonus(terrain);
+    if attack != 0 {
+        lines.push(format!("Attack in {}: {:+}%", terrain.name, attack));
+    }
+    lines
+}
 
 #[derive(Debug)]
 pub struct InfoBox {
@@ -21,6 +41,9 @@ pub struct InfoBox {
     faction_labels: HashMap<Faction, Label>,
     number_labels: Vec<Label>,
     max_num_width: u32,
+    /// What is shown about the unit under the cursor, and its labels.
+    unit_lines: Vec<String>,
+    unit_labels: Vec<Label>,
 }
 
 impl InfoBox {
@@ -59,10 +82,12 @@ impl InfoBox {
             faction_labels: faction_labels,
             number_labels: number_labels,
             max_num_width: max_width,
+            unit_lines: Vec::new(),
+            unit_labels: Vec::new(),
         }
     }
 
-    pub fn render(&mut self, state: &State, renderer: &mut Renderer) {
+    pub fn render(&mut self, state: &State, cursor: (u32, u32), renderer: &mut Renderer) {
         // Render which faction's turn it is.
         // Render the amount of actions left somewhere.
         let (x, y) = POS;
@@ -84,5 +109,24 @@ impl InfoBox {
             .get_mut(state.turn_info.actions_left() as usize)
             .expect("Invalid number of actions left")
             .render(renderer, right, second);
+
+        // Render what the unit under the cursor is up against.
+        let lines = describe_unit(state, cursor);
+        if lines != self.unit_lines {
+            let font = state.resources.font(FIRA_SANS_PATH, 16);
+            self.unit_labels = lines.iter()
+                .map(|line| Label::new(&font, line, TEXT_COLOR, state.resources.device()))
+                .collect();
+            self.unit_lines = lines;
+        }
+        if !self.unit_labels.is_empty() {
+            let top = y + (SIZE.1 + UNIT_GAP) as i32;
+            let height = self.line_spacing * self.unit_labels.len() as u32;
+            renderer.set_draw_color(BG_COLOR);
+            renderer.fill_rect(Rect::new(x - 5, top, SIZE.0, height)).unwrap();
+            for (i, label) in self.unit_labels.iter().enumerate() {
+                label.render(renderer, x, top + (i as u32 * self.line_spacing) as i32);
+            }
+        }
     }
 }
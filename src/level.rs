@@ -1,12 +1,15 @@
 use std::cmp::{self, Ord, Ordering, PartialOrd};
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
-use spec::LevelSpec;
+use spec::{LayerSpec, LevelSpec};
 
+use dice::Dice;
+use edge::EdgeKind;
 use faction::Faction;
 use grid::Grid;
 use info::GameInfo;
 use tile::Tile;
+use topology::{Topology, Wrap};
 use unit::Unit;
 
 #[derive(Clone, Copy, Debug)]
@@ -36,12 +39,26 @@ impl Ord for Point {
 }
 
 #[inline]
-fn to_faction(code: u32) -> Option<Faction> {
+pub fn faction_from_code(code: u32) -> Result<Option<Faction>, String> {
     match code {
-        0 => None,
-        1 => Some(Faction::Red),
-        2 => Some(Faction::Blue),
-        _ => panic!("unrecognized faction with code {}", code),
+        0 => Ok(None),
+        1 => Ok(Some(Faction::Red)),
+        2 => Ok(Some(Faction::Blue)),
+        _ => Err(format!("unrecognized faction with code {}", code)),
+    }
+}
+
+#[inline]
+fn to_faction(code: u32) -> Option<Faction> {
+    faction_from_code(code).unwrap_or_else(|err| panic!("{}", err))
+}
+
+#[inline]
+pub fn faction_code(faction: Option<Faction>) -> u32 {
+    match faction {
+        None => 0,
+        Some(Faction::Red) => 1,
+        Some(Faction::Blue) => 2,
     }
 }
 
@@ -51,6 +68,15 @@ pub type Layer = HashMap<String, BTreeSet<Point>>;
 pub struct Level {
     pub name: String,
     pub schema: String,
+    pub topology: Topology,
+    /// The extra cost of moving diagonally, if units may.
+    pub diagonal_cost: Option<u32>,
+    pub wrap: Wrap,
+    /// How much damage may randomly vary either way, in percent.
+    pub luck: u32,
+    /// Where the dice for luck start, if the level fixes it. Otherwise it
+    /// is up to whoever starts the game to pick one.
+    pub seed: Option<u32>,
     pub layers: HashMap<String, Layer>,
 }
 
@@ -65,14 +91,175 @@ impl Level {
                 (k, v)
             })
             .collect();
-        Ok(Level {
+        let topology = match spec.topology {
+            Some(ref name) => {
+                Topology::from_name(name)
+                    .ok_or_else(|| format!("unrecognized topology {:?}", name))?
+            }
+            None => Topology::Square,
+        };
+        if spec.diagonal_cost.is_some() && topology != Topology::Square {
+            return Err("diagonal moves are only allowed on square grids".to_owned());
+        }
+        let wrap = match spec.wrap {
+            Some(ref name) => {
+                Wrap::from_name(name).ok_or_else(|| format!("unrecognized wrap {:?}", name))?
+            }
+            None => Wrap::None,
+        };
+        let luck = spec.luck.unwrap_or(0);
+        if luck > 100 {
+            return Err(format!("luck cannot be more than 100%, not {}%", luck));
+        }
+        let level = Level {
             name: spec.name,
             schema: spec.schema,
+            topology: topology,
+            diagonal_cost: spec.diagonal_cost,
+            wrap: wrap,
+            luck: luck,
+            seed: spec.seed,
             layers: layers,
-        })
+        };
+        if let (Topology::Hex, true, Some((_, (_, h)))) =
+               (topology, wrap.vertical(), level.bounds()) {
+            if h % 2 != 0 {
+                // Otherwise the rows would not line up across the edge.
+                return Err(format!("hex maps that wrap vertically need an even number of rows, \
+                                    not {}",
+                                   h));
+            }
+        }
+        Ok(level)
     }
 
-    pub fn create_grid(&self, info: &GameInfo) -> Grid {
+    /// Creates a level describing the terrain, units and ownership of a grid.
+    ///
+    /// Every tile is included, so that the level keeps the size and offset of
+    /// the grid.
+    pub fn from_grid(grid: &Grid, name: String, schema: String) -> Level {
+        let mut terrain = Layer::new();
+        let mut units = Layer::new();
+        let (w, h) = grid.size();
+        let (min_x, min_y) = grid.offset();
+        for y in 0..h {
+            for x in 0..w {
+                let (unit, tile) = grid.unit_and_tile((x, y));
+                let (lx, ly) = (x as i32 + min_x, y as i32 + min_y);
+                terrain.entry(tile.terrain.name.clone())
+                    .or_insert_with(BTreeSet::new)
+                    .insert(Point(lx, ly, faction_code(tile.faction)));
+                if let Some(unit) = unit {
+                    units.entry(unit.kind.name.clone())
+                        .or_insert_with(BTreeSet::new)
+                        .insert(Point(lx, ly, faction_code(Some(unit.faction))));
+                }
+            }
+        }
+        // Heights are all stored under one name, with flat ground left out.
+        let mut elevation = Layer::new();
+        for y in 0..h {
+            for x in 0..w {
+                let height = grid.tile((x, y)).elevation;
+                if height != 0 {
+                    elevation.entry("height".to_owned())
+                        .or_insert_with(BTreeSet::new)
+                        .insert(Point(x as i32 + min_x, y as i32 + min_y, height));
+                }
+            }
+        }
+
+        let mut teleporters = Layer::new();
+        for y in 0..h {
+            for x in 0..w {
+                let to = match grid.teleporter((x, y)) {
+                    Some((to, _)) if (x, y) < to => to,
+                    _ => continue,
+                };
+                let name = format!("{}", teleporters.len() + 1);
+                let mut pair = BTreeSet::new();
+                for &(from, to) in &[((x, y), to), (to, (x, y))] {
+                    let cost = match grid.teleporter(from) {
+                        Some((dest, cost)) if dest == to => cost,
+                        _ => continue,
+                    };
+                    pair.insert(Point(from.0 as i32 + min_x, from.1 as i32 + min_y, cost));
+                }
+                teleporters.insert(name, pair);
+            }
+        }
+
+        // Each tile's sides of one kind are stored as a bitmask of directions.
+        let mut sides = BTreeMap::new();
+        for (pos, dir, kind) in grid.edges() {
+            *sides.entry((kind.name(), pos)).or_insert(0) |= 1 << dir;
+        }
+        let mut edges = Layer::new();
+        for ((name, (x, y)), mask) in sides {
+            edges.entry(name.to_owned())
+                .or_insert_with(BTreeSet::new)
+                .insert(Point(x as i32 + min_x, y as i32 + min_y, mask));
+        }
+
+        let mut layers = HashMap::new();
+        layers.insert("terrain".to_owned(), terrain);
+        layers.insert("units".to_owned(), units);
+        if !elevation.is_empty() {
+            layers.insert("elevation".to_owned(), elevation);
+        }
+        if !teleporters.is_empty() {
+            layers.insert("teleporters".to_owned(), teleporters);
+        }
+        if !edges.is_empty() {
+            layers.insert("edges".to_owned(), edges);
+        }
+        Level {
+            name: name,
+            schema: schema,
+            topology: grid.topology(),
+            diagonal_cost: grid.diagonal_cost(),
+            wrap: grid.wrap(),
+            luck: grid.luck(),
+            // So that a saved game carries on with the same rolls.
+            seed: Some(grid.dice().state()),
+            layers: layers,
+        }
+    }
+
+    pub fn to_spec(&self) -> LevelSpec {
+        let layers = self.layers
+            .iter()
+            .map(|(k, v)| {
+                let v = v.iter()
+                    .map(|(k, v)| (k.clone(), v.iter().map(|p| (p.0, p.1, p.2)).collect()))
+                    .collect::<LayerSpec>();
+                (k.clone(), v)
+            })
+            .collect();
+        let topology = match self.topology {
+            Topology::Square => None,
+            topology => Some(topology.name().to_owned()),
+        };
+        let wrap = match self.wrap {
+            Wrap::None => None,
+            wrap => Some(wrap.name().to_owned()),
+        };
+        LevelSpec {
+            name: self.name.clone(),
+            schema: self.schema.clone(),
+            topology: topology,
+            diagonal_cost: self.diagonal_cost,
+            wrap: wrap,
+            luck: if self.luck == 0 { None } else { Some(self.luck) },
+            seed: if self.luck == 0 { None } else { self.seed },
+            layers: layers,
+        }
+    }
+
+    /// Returns the lowest coordinates used by the level, and its size.
+    ///
+    /// Returns `None` if the level is empty.
+    pub fn bounds(&self) -> Option<((i32, i32), (u32, u32))> {
         let mut min_x = i32::max_value();
         let mut max_x = i32::min_value();
         let mut min_y = i32::max_value();
@@ -89,8 +276,16 @@ impl Level {
             }
         }
 
+        if min_x > max_x {
+            return None;
+        }
         let w = (max_x - min_x + 1) as u32;
         let h = (max_y - min_y + 1) as u32;
+        Some(((min_x, min_y), (w, h)))
+    }
+
+    pub fn create_grid(&self, info: &GameInfo) -> Grid {
+        let ((min_x, min_y), (w, h)) = self.bounds().expect("level is empty");
 
         let mut grid = if let Some(layer) = self.layers.get("terrain") {
             Grid::new((w, h), |(x, y)| {
@@ -109,6 +304,7 @@ impl Level {
                                 terrain: terrain.clone(),
                                 faction: faction,
                                 capture: None,
+                                elevation: 0,
                             };
                         } else {
                             panic!("terrain not in info file: {:?}", tile);
@@ -119,6 +315,7 @@ impl Level {
                     terrain: info.terrain["default"].clone(),
                     faction: None,
                     capture: None,
+                    elevation: 0,
                 }
             })
         } else {
@@ -127,10 +324,22 @@ impl Level {
                     terrain: info.terrain["default"].clone(),
                     faction: None,
                     capture: None,
+                    elevation: 0,
                 }
             })
         };
 
+        if let Some(layer) = self.layers.get("elevation") {
+            for positions in layer.values() {
+                for &Point(x, y, height) in positions {
+                    let pos = ((x - min_x) as u32, (y - min_y) as u32);
+                    grid.tile_mut(pos).elevation = height;
+                }
+            }
+        }
+        grid.set_elevation_rules(info.elevation.clone());
+        grid.set_combat_rules(info.combat.clone());
+
         for (tile, positions) in &self.layers["units"] {
             let kind = match info.unit_kinds.get(&tile[..]) {
                 Some(kind) => kind,
@@ -143,6 +352,46 @@ impl Level {
                 grid.add_unit(unit, pos);
             }
         }
+        if let Some(layer) = self.layers.get("teleporters") {
+            for (name, positions) in layer {
+                let pair = positions.iter()
+                    .map(|&Point(x, y, cost)| (((x - min_x) as u32, (y - min_y) as u32), cost))
+                    .collect::<Vec<_>>();
+                if pair.len() != 2 {
+                    warn!("Teleporter {:?} has {} ends instead of 2", name, pair.len());
+                    continue;
+                }
+                let ((a, cost_a), (b, cost_b)) = (pair[0], pair[1]);
+                grid.set_teleporter(a, b, cost_a);
+                grid.set_teleporter(b, a, cost_b);
+            }
+        }
+
+        if let Some(layer) = self.layers.get("edges") {
+            for (name, positions) in layer {
+                let kind = match EdgeKind::from_name(name) {
+                    Some(kind) => kind,
+                    None => {
+                        warn!("Unrecognized edge kind {:?}", name);
+                        continue;
+                    }
+                };
+                for &Point(x, y, mask) in positions {
+                    let pos = ((x - min_x) as u32, (y - min_y) as u32);
+                    for dir in 0..self.topology.direction_count() {
+                        if mask & (1 << dir) != 0 {
+                            grid.set_edge(pos, dir, Some(kind));
+                        }
+                    }
+                }
+            }
+        }
+
+        grid.set_offset((min_x, min_y));
+        grid.set_topology(self.topology);
+        grid.set_diagonal_cost(self.diagonal_cost);
+        grid.set_wrap(self.wrap);
+        grid.set_luck(self.luck, Dice::new(self.seed.unwrap_or(0)));
         grid
     }
 }
@@ -5,7 +5,7 @@ use std::io::{self, Read};
 use std::path::Path;
 
 use json;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use toml;
 
 #[derive(Debug)]
@@ -69,7 +69,7 @@ fn make_context(err: &toml::ParserError, contents: &str, ctx: &mut String) {
     }
 }
 
-pub fn load_toml<T, P, F>(path: P, mut warn: F) -> Result<T, Error>
+pub fn load_toml<T, P, F>(path: P, warn: F) -> Result<T, Error>
     where T: Deserialize,
           P: AsRef<Path>,
           F: FnMut(&str)
@@ -78,12 +78,19 @@ pub fn load_toml<T, P, F>(path: P, mut warn: F) -> Result<T, Error>
     File::open(path)
         .and_then(|mut file| file.read_to_string(&mut contents))
         .map_err(Error::Read)?;
-    let mut parser = toml::Parser::new(&contents);
+    parse_toml(&contents, warn)
+}
+
+pub fn parse_toml<T, F>(contents: &str, mut warn: F) -> Result<T, Error>
+    where T: Deserialize,
+          F: FnMut(&str)
+{
+    let mut parser = toml::Parser::new(contents);
 
     let table = parser.parse();
     for warning in &parser.errors {
         let mut msg = format!("parsing file: {}\n", warning);
-        make_context(warning, &contents, &mut msg);
+        make_context(warning, contents, &mut msg);
         warn(&msg);
     }
     let table = table.ok_or(Error::Parse)?;
@@ -104,3 +111,10 @@ pub fn load_json<T, P>(path: P) -> Result<T, json::Error>
 {
     json::from_reader(File::open(path)?)
 }
+
+pub fn save_json<T, P>(path: P, value: &T) -> Result<(), json::Error>
+    where T: Serialize,
+          P: AsRef<Path>
+{
+    json::to_writer(&mut File::create(path)?, value)
+}
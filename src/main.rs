@@ -18,6 +18,8 @@ extern crate toml;
 extern crate spec;
 
 use std::env;
+use std::io::{self, Write};
+use std::path::PathBuf;
 use std::process;
 
 use glorious::{BoxedInputMapper, Color, Device, Game, ResourceManager};
@@ -26,16 +28,27 @@ use sdl2::mouse::Mouse;
 use sdl2::render::BlendMode;
 use sdl2_image::{INIT_JPG, INIT_PNG};
 
-use common::{Config, State};
-use faction::Faction;
+use cli::{Command, Options, Player, USAGE};
+use common::{Config, State, TurnInfo};
+use editor::Editor;
 use info::GameInfo;
 use level::Level;
 use load::load_toml;
-use resources::FIRA_SANS_BOLD_PATH;
+use resources::{FIRA_SANS_BOLD_PATH, FIRA_SANS_PATH};
 use scene::Scene;
+use turn_file::PlayByEmail;
 
+mod action;
+mod ai;
+mod check;
+mod cli;
 mod common;
+mod dice;
+mod edge;
+mod editor;
 mod faction;
+#[cfg(test)]
+mod fixtures;
 mod grid;
 mod grid_manager;
 mod info;
@@ -45,9 +58,12 @@ mod load;
 mod menus;
 mod range;
 mod resources;
+mod save;
 mod scene;
 mod target_selector;
 mod tile;
+mod topology;
+mod turn_file;
 mod unit;
 mod unit_mover;
 
@@ -60,7 +76,16 @@ fn main() {
     const WINDOW_TITLE: &'static str = "Raccoon Squad";
     const TILE_SIZE: (u32, u32) = (48, 48);
     const MAX_FPS: u32 = 60;
-    const NUMBER_OF_ACTIONS: u32 = 4;
+    const SAVE_PATH: &'static str = "savegame.json";
+
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            writeln!(io::stderr(), "{}\n{}", err, USAGE).unwrap();
+            process::exit(2);
+        }
+    };
+    let edit = options.command == Command::Edit;
 
     // Set up logging.
 
@@ -80,23 +105,70 @@ fn main() {
     }
     builder.init().unwrap();
 
+    if options.command == Command::Check {
+        process::exit(check::run(&options.level_path, &options.info_path));
+    }
+
     // Load level
 
-    let info = match load_toml("info.toml", |m| warn!("{}", m)) {
+    let info = match load_toml(&options.info_path, |m| warn!("{}", m)) {
         Ok(spec) => GameInfo::from_spec(spec).expect("could not validate info file"),
         Err(err) => {
             error!("could not load info file: {}", err);
             process::exit(1);
         }
     };
-    let level = match load::load_json("level.json") {
-        Ok(spec) => Level::from_spec(spec).expect("could not validate level"),
-        Err(err) => {
-            error!("could not load level: {}", err);
-            process::exit(1);
+    let factions = options.players.iter().map(|&(f, _)| f).collect::<Vec<_>>();
+    let ai_factions = options.players
+        .iter()
+        .filter(|&&(_, p)| p == Player::Ai)
+        .map(|&(f, _)| f)
+        .collect::<Vec<_>>();
+
+    // Resume the saved game if there is one, otherwise start the level.
+    let resume = !edit && options.save_path.as_ref().map_or(false, |p| p.exists());
+    let (level, grid, turn_info) = if resume {
+        let path = options.save_path.as_ref().unwrap();
+        if options.level_given {
+            warn!("ignoring level {}, resuming the saved game in {} instead",
+                  options.level_path.display(),
+                  path.display());
+        }
+        if options.seed.is_some() {
+            warn!("ignoring --seed, the saved game carries on with its own dice");
         }
+        match save::load_game(path, &info, options.actions_per_turn) {
+            Ok(game) => game,
+            Err(err) => {
+                error!("could not load saved game: {}", err);
+                process::exit(1);
+            }
+        }
+    } else {
+        let mut level = match load::load_json(&options.level_path) {
+            Ok(spec) => Level::from_spec(spec).expect("could not validate level"),
+            Err(err) => {
+                error!("could not load level: {}", err);
+                process::exit(1);
+            }
+        };
+        if options.seed.is_some() {
+            level.seed = options.seed;
+        }
+        if !edit && level.luck > 0 && level.seed.is_none() {
+            if options.turn_dir.is_some() {
+                // Each machine would roll its own dice.
+                error!("playing a level with luck by email needs the same --seed on every \
+                        machine");
+                process::exit(1);
+            }
+            let seed = rand::random();
+            info!("Rolling the dice with seed {}", seed);
+            level.seed = Some(seed);
+        }
+        let grid = level.create_grid(&info);
+        (level, grid, TurnInfo::new(factions, options.actions_per_turn))
     };
-    let grid = level.create_grid(&info);
 
     // Set up SDL2.
 
@@ -107,7 +179,8 @@ fn main() {
     let font_context = sdl2_ttf::init().expect("Font init");
     // let mut limiter = FrameLimiter::new(60);
 
-    let window = video_subsystem.window(WINDOW_TITLE, 1008, 720)
+    let (window_w, window_h) = options.window_size;
+    let window = video_subsystem.window(WINDOW_TITLE, window_w, window_h)
         .allow_highdpi()
         .position_centered()
         .opengl()
@@ -126,21 +199,22 @@ fn main() {
 
     // Set up game state.
 
-    let config = Config {};
+    let config = Config {
+        level_name: level.name.clone(),
+        level_schema: level.schema.clone(),
+        save_path: options.save_path.clone().unwrap_or_else(|| PathBuf::from(SAVE_PATH)),
+        ai_factions: ai_factions,
+        turn_dir: options.turn_dir.clone(),
+    };
 
     let health_label_font = resources.font(FIRA_SANS_BOLD_PATH, 13);
     let mut state = State::new(resources,
                                grid,
                                TILE_SIZE,
-                               vec![Faction::Red, Faction::Blue],
-                               NUMBER_OF_ACTIONS,
+                               turn_info,
                                &health_label_font,
                                config);
 
-    // Prepare the scene
-
-    let mut scene = Scene::new(&state);
-
     // Set up input handling.
 
     let mut mapper = BoxedInputMapper::new();
@@ -158,6 +232,9 @@ fn main() {
     mapper.add(map_scan_pressed!(Scancode::D, MoveCameraRight));
 
     mapper.add(map_scan_pressed!(Scancode::Space, FinishTurn));
+    mapper.add(map_scan_pressed!(Scancode::F5, SaveGame));
+    mapper.add(map_scan_pressed!(Scancode::G, GoTo));
+    mapper.add(map_scan_pressed!(Scancode::T, ToggleThreats));
     mapper.add(map_scan_pressed!(Scancode::Z, Confirm));
     mapper.add(map_scan_pressed!(Scancode::X, Cancel));
     mapper.add(map_scan_released!(Scancode::X, CancelReleased));
@@ -186,11 +263,45 @@ fn main() {
         MouseScroll(x, y)
     ));
 
+    if edit {
+        mapper.add(map_scan_pressed!(Scancode::E, NextBrush));
+        mapper.add(map_scan_pressed!(Scancode::Q, PrevBrush));
+        mapper.add(map_scan_pressed!(Scancode::C, NextBrushFaction));
+        mapper.add(map_scan_pressed!(Scancode::RightBracket, ResizeMap(1, 0)));
+        mapper.add(map_scan_pressed!(Scancode::LeftBracket, ResizeMap(-1, 0)));
+        mapper.add(map_scan_pressed!(Scancode::Equals, ResizeMap(0, 1)));
+        mapper.add(map_scan_pressed!(Scancode::Minus, ResizeMap(0, -1)));
+        mapper.add(map_scan_pressed!(Scancode::Return, SaveLevel));
+    }
+
     // Run the main loop.
 
     let event_pump = sdl_context.event_pump().unwrap();
     let mut game =
         Game::with_clear_color(Color(0x66, 0x66, 0x66, 0xff), MAX_FPS, renderer, event_pump);
 
-    game.run(&mut state, &mapper, &mut scene, |m| *m == Exit);
+    if edit {
+        let font = state.resources.font(FIRA_SANS_PATH, 16);
+        let mut editor = Editor::new(&font, &info, &level, options.level_path.clone(), &state);
+        game.run(&mut state, &mapper, &mut editor, |m| *m == Exit);
+    } else {
+        // Catch up on turns played by email.
+
+        let play_by_email = match state.config.turn_dir.clone() {
+            Some(dir) => {
+                let local = options.play_as.expect("playing by email as nobody");
+                match PlayByEmail::catch_up(dir, local, &mut state.grid, &mut state.turn_info) {
+                    Ok(play_by_email) => Some(play_by_email),
+                    Err(err) => {
+                        error!("could not replay turn files: {}", err);
+                        process::exit(1);
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let mut scene = Scene::new(&state, play_by_email);
+        game.run(&mut state, &mapper, &mut scene, |m| *m == Exit);
+    }
 }
@@ -1,4 +1,8 @@
+use std::cmp;
+use std::vec;
+
 use grid::Grid;
+use topology::Metric;
 use unit::Unit;
 
 #[derive(Clone)]
@@ -32,27 +36,62 @@ impl<'a> AttackRange<'a> {
     }
 
     #[inline]
-    pub fn ranged(grid: &'a Grid, pos: (u32, u32), min: u32, max: u32) -> AttackRange<'a> {
+    pub fn ranged(grid: &'a Grid,
+                  pos: (u32, u32),
+                  min: u32,
+                  max: u32,
+                  metric: Metric)
+                  -> AttackRange<'a> {
+        // High ground reaches further, but only down to lower targets.
+        let bonus = grid.elevation_rules().range;
+        let highest = max.saturating_add(bonus.saturating_mul(grid.tile(pos).elevation));
         AttackRange {
             kind: Kind::Ranged(Ranged {
                 grid: grid,
                 pos: pos,
                 min: min,
-                cur: (0, max as i32),
+                max: max,
+                metric: metric,
+                sight: true,
+                radius: highest,
+                ring: Vec::new().into_iter(),
             }),
         }
     }
 
+    /// Like a ranged attack, but lobbed over whatever is in the way.
     #[inline]
-    pub fn spear(grid: &'a Grid, unit: &'a Unit, pos: (u32, u32), max: u32) -> AttackRange<'a> {
+    pub fn area(grid: &'a Grid,
+                pos: (u32, u32),
+                min: u32,
+                max: u32,
+                metric: Metric)
+                -> AttackRange<'a> {
+        let mut range = AttackRange::ranged(grid, pos, min, max, metric);
+        if let Kind::Ranged(ref mut it) = range.kind {
+            it.sight = false;
+        }
+        range
+    }
+
+    #[inline]
+    pub fn spear(grid: &'a Grid,
+                 unit: &'a Unit,
+                 weapon: usize,
+                 pos: (u32, u32),
+                 max: u32)
+                 -> AttackRange<'a> {
         AttackRange {
             kind: Kind::Spear(Spear {
                 grid: grid,
                 unit: unit,
+                weapon: weapon,
                 pos: pos,
                 max: max,
                 state: 0,
                 dist: 0,
+                cur: pos,
+                pierced: 0,
             }),
         }
     }
@@ -75,32 +114,24 @@ impl<'a> Iterator for AttackRange<'a> {
 struct Melee<'a> {
     grid: &'a Grid,
     pos: (u32, u32),
-    state: u8,
+    state: usize,
 }
 
 impl<'a> Iterator for Melee<'a> {
     type Item = (u32, u32);
 
     fn next(&mut self) -> Option<(u32, u32)> {
-        let (x, y) = self.pos;
-        let (w, h) = self.grid.size();
-        loop {
-            let (dx, dy) = match self.state {
-                0 => (0, 1),
-                1 => (1, 0),
-                2 => (0, -1),
-                3 => (-1, 0),
-                _ => return None,
-            };
+        while self.state < self.grid.topology().direction_count() {
+            let dir = self.state;
             self.state += 1;
 
-            let nx = x as i32 + dx;
-            let ny = y as i32 + dy;
-
-            if 0 <= nx && nx < w as i32 && 0 <= ny && ny < h as i32 {
-                return Some((nx as u32, ny as u32));
+            if let Some(res) = self.grid.neighbor(self.pos, dir) {
+                if self.grid.can_melee_across(self.pos, dir) {
+                    return Some(res);
+                }
             }
         }
+        None
     }
 }
 
@@ -109,41 +140,61 @@ struct Ranged<'a> {
     grid: &'a Grid,
     pos: (u32, u32),
     min: u32,
-    cur: (i32, i32),
+    max: u32,
+    metric: Metric,
+    /// Whether targets must be in the line of sight.
+    sight: bool,
+    /// The radius of the next ring.
+    radius: u32,
+    ring: vec::IntoIter<(i32, i32)>,
+}
+
+impl<'a> Ranged<'a> {
+    /// Returns how far the attack reaches `target`, counting high ground.
+    #[inline]
+    fn max_towards(&self, target: (u32, u32)) -> u32 {
+        let height = cmp::max(0, self.grid.height_above(self.pos, target)) as u32;
+        let bonus = self.grid.elevation_rules().range;
+        self.max.saturating_add(bonus.saturating_mul(height))
+    }
 }
 
 impl<'a> Iterator for Ranged<'a> {
     type Item = (u32, u32);
 
     fn next(&mut self) -> Option<(u32, u32)> {
-        let (x, y) = self.pos;
-        let (w, h) = self.grid.size();
-        while self.cur != (0, self.min as i32 - 1) {
-            let (dx, dy) = self.cur;
-
-            let nx = x as i32 + dx;
-            let ny = y as i32 + dy;
-
-            self.cur = match (dx.signum(), dy.signum()) {
-                (0, 1) | (1, 1) => (dx + 1, dy - 1), // N-E
-                (1, 0) | (1, -1) => (dx - 1, dy - 1), // E-S
-                (0, -1) | (-1, -1) => (dx - 1, dy + 1), // S-W
-                (-1, 0) | (-1, 1) => {
-                    // W-N
-                    if dx == -1 {
-                        (dx + 1, dy)
-                    } else {
-                        (dx + 1, dy + 1)
+        loop {
+            let pos = match self.ring.next() {
+                Some(pos) => pos,
+                None => {
+                    // Work inwards from the furthest ring.
+                    if self.radius < self.min || self.radius == 0 {
+                        return None;
                     }
+                    let center = self.grid.to_level(self.pos);
+                    self.ring = self.grid
+                        .topology()
+                        .ring(center, self.radius, self.metric)
+                        .into_iter();
+                    self.radius -= 1;
+                    continue;
                 }
-                _ => unreachable!(),
             };
-
-            if 0 <= nx && nx < w as i32 && 0 <= ny && ny < h as i32 {
-                return Some((nx as u32, ny as u32));
+            if let Some(res) = self.grid.from_level(pos) {
+                // On small wrapping maps, several rings can lead to the same
+                // tile, even the center. Only the nearest copy counts, so
+                // every tile comes once, and no closer than `min`.
+                if pos != self.grid.to_level_near(self.pos, res) {
+                    continue;
+                }
+                // The ring just listed is one further out than `radius` now.
+                let in_reach = self.radius < self.max_towards(res);
+                let seen = !self.sight || self.grid.line_of_sight(self.pos, res);
+                if in_reach && seen {
+                    return Some(res);
+                }
             }
         }
-        None
     }
 }
 
@@ -151,45 +202,48 @@ impl<'a> Iterator for Ranged<'a> {
 struct Spear<'a> {
     grid: &'a Grid,
     unit: &'a Unit,
+    weapon: usize,
     pos: (u32, u32),
     max: u32,
-    state: u8,
+    state: usize,
     dist: u32,
+    cur: (u32, u32),
+    /// The number of enemies passed through in the current direction.
+    pierced: u32,
 }
 
 impl<'a> Iterator for Spear<'a> {
     type Item = (u32, u32);
 
     fn next(&mut self) -> Option<(u32, u32)> {
-        let (x, y) = self.pos;
-        let (w, h) = self.grid.size();
         loop {
-            let (dx, dy) = match self.state {
-                0 => (0, 1),
-                1 => (1, 0),
-                2 => (0, -1),
-                3 => (-1, 0),
-                _ => return None,
-            };
+            let dir = self.state;
+            if dir >= self.grid.topology().direction_count() {
+                return None;
+            }
             if self.dist >= self.max {
                 self.state += 1;
                 self.dist = 0;
+                self.cur = self.pos;
+                self.pierced = 0;
                 continue;
             }
             self.dist += 1;
 
-            let nx = x as i32 + dx * self.dist as i32;
-            let ny = y as i32 + dy * self.dist as i32;
-
-            if !(0 <= nx && nx < w as i32 && 0 <= ny && ny < h as i32) {
-                continue;
-            }
-            let res = (nx as u32, ny as u32);
+            let res = match self.grid.neighbor(self.cur, dir) {
+                Some(res) if self.grid.can_melee_across(self.cur, dir) => res,
+                _ => {
+                    self.dist = self.max;
+                    continue;
+                }
+            };
+            self.cur = res;
 
             if let Some(other) = self.grid.unit(res) {
-                if !self.unit.can_spear_through(other) {
-                    self.state += 1;
-                    self.dist = 0;
+                if !self.unit.can_spear_through(self.weapon, other, self.pierced) {
+                    self.dist = self.max;
+                } else if self.unit.can_attack(other) {
+                    self.pierced += 1;
                 }
             }
             return Some(res);
@@ -0,0 +1,126 @@
+use std::path::Path;
+
+use spec::{CaptureSpec, SaveSpec, UnitStateSpec};
+
+use common::{State, TurnInfo};
+use faction::Faction;
+use grid::Grid;
+use info::GameInfo;
+use level::Level;
+use load::{load_json, save_json};
+
+#[inline]
+fn to_faction(name: &str) -> Result<Faction, String> {
+    Faction::from_name(name).ok_or_else(|| format!("unrecognized faction {:?}", name))
+}
+
+/// Saves the map, units and turn of a game in progress.
+///
+/// The actions taken so far this turn are not saved, so when playing by
+/// email the game can only be saved between turns: the turn file written
+/// after loading would otherwise miss them.
+pub fn save_game<P: AsRef<Path>>(path: P, state: &State) -> Result<(), String> {
+    if state.config.turn_dir.is_some() && !state.actions.is_empty() {
+        return Err("cannot save in the middle of a turn when playing by email".to_owned());
+    }
+    let grid = &state.grid;
+    let level = Level::from_grid(grid,
+                                 state.config.level_name.clone(),
+                                 state.config.level_schema.clone());
+
+    let mut units = Vec::new();
+    let mut captures = Vec::new();
+    let (w, h) = grid.size();
+    let (min_x, min_y) = grid.offset();
+    for y in 0..h {
+        for x in 0..w {
+            let pos = (x as i32 + min_x, y as i32 + min_y);
+            let (unit, tile) = grid.unit_and_tile((x, y));
+            if let Some(unit) = unit {
+                units.push(UnitStateSpec {
+                    pos: pos,
+                    health: unit.health,
+                    spent: unit.spent,
+                    goal: unit.goal.map(|(x, y)| (x as i32 + min_x, y as i32 + min_y)),
+                    facing: Some(unit.facing),
+                });
+            }
+            if let Some((faction, progress)) = tile.capture {
+                captures.push(CaptureSpec {
+                    pos: pos,
+                    faction: faction.name().to_owned(),
+                    progress: progress,
+                });
+            }
+        }
+    }
+
+    let turn_info = &state.turn_info;
+    let spec = SaveSpec {
+        level: level.to_spec(),
+        units: units,
+        captures: captures,
+        factions: turn_info.factions().iter().map(|f| f.name().to_owned()).collect(),
+        current: turn_info.current_faction().name().to_owned(),
+        turn: turn_info.turn(),
+        actions_left: turn_info.actions_left(),
+    };
+    save_json(path, &spec).map_err(|err| format!("{}", err))
+}
+
+/// Loads a game saved with `save_game`.
+pub fn load_game<P: AsRef<Path>>(path: P,
+                                 info: &GameInfo,
+                                 max_actions_left: u32)
+                                 -> Result<(Level, Grid, TurnInfo), String> {
+    let spec: SaveSpec = load_json(path).map_err(|err| format!("{}", err))?;
+    let level = Level::from_spec(spec.level)?;
+    let mut grid = level.create_grid(info);
+
+    let (w, h) = grid.size();
+    let (min_x, min_y) = grid.offset();
+    let to_grid = |pos: (i32, i32)| {
+        let (x, y) = (pos.0 - min_x, pos.1 - min_y);
+        if 0 <= x && x < w as i32 && 0 <= y && y < h as i32 {
+            Ok((x as u32, y as u32))
+        } else {
+            Err(format!("position {:?} is outside the map", pos))
+        }
+    };
+
+    let direction_count = grid.topology().direction_count();
+    for state in spec.units {
+        let goal = match state.goal {
+            Some(goal) => Some(to_grid(goal)?),
+            None => None,
+        };
+        let facing = state.facing.unwrap_or(0);
+        if facing >= direction_count {
+            return Err(format!("unrecognized facing {}", facing));
+        }
+        if state.health == 0 || state.health > 10 {
+            return Err(format!("invalid health {}", state.health));
+        }
+        let unit = grid.unit_mut(to_grid(state.pos)?)
+            .ok_or_else(|| format!("no unit at {:?}", state.pos))?;
+        unit.health = state.health;
+        unit.spent = state.spent;
+        unit.goal = goal;
+        unit.facing = facing;
+    }
+    for capture in spec.captures {
+        let faction = to_faction(&capture.faction)?;
+        grid.tile_mut(to_grid(capture.pos)?).capture = Some((faction, capture.progress));
+    }
+
+    let factions = spec.factions
+        .iter()
+        .map(|name| to_faction(name))
+        .collect::<Result<Vec<_>, String>>()?;
+    let turn_info = TurnInfo::resume(factions,
+                                     to_faction(&spec.current)?,
+                                     spec.turn,
+                                     spec.actions_left,
+                                     max_actions_left)?;
+    Ok((level, grid, turn_info))
+}
@@ -1,25 +1,150 @@
+use std::mem;
+use std::time::{Duration, Instant};
+
 use glorious::{Behavior, Renderer};
 
+use action::Action;
+use ai;
 use common::{ModalBox, Message, State};
-use grid_manager::GridManager;
+use grid_manager::{give_order, GridManager};
 use info_box::InfoBox;
 use resources::FIRA_SANS_PATH;
+use save;
+use turn_file::PlayByEmail;
+use unit_mover::UnitMover;
 
 #[derive(Debug)]
 pub struct Scene {
     grid_manager: GridManager,
     info_box: InfoBox,
     modal_stack: Vec<ModalBox>,
+    play_by_email: Option<PlayByEmail>,
+    prev_ai_time: Instant,
 }
 
+/// How long the computer waits between its actions, in milliseconds.
+const AI_DELAY_MS: u64 = 500;
+
 impl Scene {
     #[inline]
-    pub fn new(state: &State) -> Self {
+    pub fn new(state: &State, play_by_email: Option<PlayByEmail>) -> Self {
         let (w, h) = state.grid.size();
         Scene {
             grid_manager: GridManager::new((w / 2, h / 2)),
             info_box: InfoBox::new(&state.resources.font(FIRA_SANS_PATH, 16), state),
             modal_stack: Vec::new(),
+            play_by_email: play_by_email,
+            prev_ai_time: Instant::now(),
+        }
+    }
+
+    #[inline]
+    fn is_waiting(&self) -> bool {
+        self.play_by_email.as_ref().map_or(false, |p| p.is_waiting())
+    }
+
+    /// Whether the current faction is played by someone else than the
+    /// person in front of the screen.
+    #[inline]
+    fn is_blocked(&self, state: &State) -> bool {
+        self.is_waiting() || state.config.is_ai(state.turn_info.current_faction())
+    }
+
+    fn finish_turn(&mut self, state: &mut State) {
+        self.grid_manager.deselect();
+        self.grid_manager.grid_changed();
+        let turn = state.turn_info.turn();
+        let faction = state.turn_info.current_faction();
+        let actions = mem::replace(&mut state.actions, Vec::new());
+        state.grid.ready_units();
+        state.turn_info.end_turn();
+        if let Some(ref mut play_by_email) = self.play_by_email {
+            if let Err(err) = play_by_email.turn_finished(turn,
+                                                          faction,
+                                                          actions,
+                                                          &state.grid,
+                                                          &state.turn_info) {
+                error!("Could not write turn file: {}", err);
+            }
+        }
+        // TODO: Display a turn change animation here
+    }
+
+    /// Moves the next unit with a go-to order along its route, once per
+    /// turn, returning the animation of its move. Each step costs an
+    /// action, like moving the unit by hand would.
+    fn advance_orders(&mut self, state: &mut State) -> Option<ModalBox> {
+        let (w, h) = state.grid.size();
+        for y in 0..h {
+            for x in 0..w {
+                if state.turn_info.actions_left() == 0 {
+                    return None;
+                }
+                let pos = (x, y);
+                let goal = match state.grid.unit(pos) {
+                    Some(unit) if state.turn_info.can_act(unit) => unit.goal,
+                    _ => None,
+                };
+                let goal = match goal {
+                    Some(goal) => goal,
+                    None => continue,
+                };
+
+                let (action, path) = match state.grid.step_towards(pos, goal) {
+                    Some(to) if to != pos => {
+                        let action = Action::Wait {
+                            from: pos,
+                            to: to,
+                            facing: None,
+                        };
+                        (action, state.grid.path_finder(pos).path(to))
+                    }
+                    _ => {
+                        info!("Unit at {:?} is blocked on its way to {:?}", pos, goal);
+                        give_order(pos, None, state).expect("could not cancel go-to order");
+                        continue;
+                    }
+                };
+                if let Err(err) = action.apply(&mut state.grid, &mut state.turn_info) {
+                    error!("Could not follow go-to order: {}", err);
+                    give_order(pos, None, state).expect("could not cancel go-to order");
+                    continue;
+                }
+                state.actions.push(action);
+                self.grid_manager.deselect();
+
+                // The move is done; lift the unit off the grid to show it.
+                let path = path.expect("no path along route");
+                let to = *path.last().expect("empty path along route");
+                let unit = state.grid.remove_unit(to);
+                return Some(Box::new(UnitMover::following_order(unit, pos, path)));
+            }
+        }
+        None
+    }
+
+    /// Lets the computer perform one action, or end its turn.
+    fn play_ai(&mut self, state: &mut State) {
+        let now = Instant::now();
+        if now.duration_since(self.prev_ai_time) < Duration::from_millis(AI_DELAY_MS) {
+            return;
+        }
+        self.prev_ai_time = now;
+
+        match ai::next_action(&state.grid, &state.turn_info) {
+            Some(action) => {
+                match action.apply(&mut state.grid, &mut state.turn_info) {
+                    Ok(()) => {
+                        state.actions.push(action);
+                        self.grid_manager.grid_changed();
+                    }
+                    Err(err) => {
+                        error!("AI chose an illegal action {:?}: {}", action, err);
+                        self.finish_turn(state);
+                    }
+                }
+            }
+            None => self.finish_turn(state),
         }
     }
 }
@@ -29,16 +154,17 @@ impl<'a> Behavior<State<'a>> for Scene {
 
     /// Updates the object each frame.
     fn update(&mut self, state: &mut State<'a>, queue: &mut Vec<Message>) {
-        let mut defeated = Vec::new();
-        for &faction in state.turn_info.factions() {
-            if state.grid.units().all(|u| u.faction != faction) {
-                defeated.push(faction);
+        if let Some(ref mut play_by_email) = self.play_by_email {
+            if play_by_email.poll(&mut state.grid, &mut state.turn_info) {
+                self.grid_manager.grid_changed();
             }
         }
+
+        let defeated = state.turn_info.remove_defeated(&state.grid);
         if !defeated.is_empty() {
+            self.grid_manager.grid_changed();
             for faction in defeated {
                 info!("Faction defeated: {:?}", faction);
-                state.turn_info.remove_faction(faction);
             }
             match state.turn_info.factions().split_last() {
                 None => info!("No contest; everybody loses."),
@@ -50,6 +176,17 @@ impl<'a> Behavior<State<'a>> for Scene {
             }
         }
 
+        let playing = state.turn_info.factions().len() > 1;
+        if playing && self.modal_stack.is_empty() && !self.is_waiting() &&
+           state.config.is_ai(state.turn_info.current_faction()) {
+            self.play_ai(state);
+        } else if playing && self.modal_stack.is_empty() && !self.is_blocked(state) &&
+                  state.active_unit.is_none() {
+            if let Some(modal) = self.advance_orders(state) {
+                state.push_modal(modal, queue);
+            }
+        }
+
         self.grid_manager.update(state);
         if let Some(modal) = self.modal_stack.last_mut() {
             modal.update(state, queue);
@@ -67,11 +204,22 @@ impl<'a> Behavior<State<'a>> for Scene {
         if state.will_pop_modals > 0 {
             return;
         }
+        if self.is_blocked(state) {
+            match message {
+                Confirm | Cancel | FinishTurn | LeftClickAt(..) | RightClickAt(..) => return,
+                _ => {}
+            }
+        }
         if let Some(modal) = self.modal_stack.last_mut() {
             modal.handle(state, message, queue);
             return;
         }
 
+        if let FinishTurn = message {
+            self.finish_turn(state);
+            return;
+        }
+
         let manager = &mut self.grid_manager;
         match message {
             // Input
@@ -96,23 +244,42 @@ impl<'a> Behavior<State<'a>> for Scene {
             MoveCameraRight => state.translate_camera((1, 0)),
 
             // Modal messages
-            AttackSelected(pos, target) => {
+            AttackSelected(pos, target, weapon) => {
                 // manager.cursor.pos = target;
-                let modal = manager.select_target(pos, target, state);
+                let modal = manager.select_target(pos, target, weapon, state);
                 // TODO
                 state.push_modal(modal, queue);
             }
-            CaptureSelected(pos) => manager.capture_at(pos, state),
-            WaitSelected => {
+            CaptureSelected(origin, pos) => {
+                let facing = state.grid.unit(pos).map(|unit| unit.facing);
+                manager.capture_at(pos, state);
+                state.actions.push(Action::Capture {
+                    from: origin,
+                    to: pos,
+                    facing: facing,
+                });
+            }
+            WaitSelected(origin, pos) => {
                 // manager.cursor.pos = target;
                 manager.hide_cursor();
+                state.actions.push(Action::Wait {
+                    from: origin,
+                    to: pos,
+                    facing: state.grid.unit(pos).map(|unit| unit.facing),
+                });
+            }
+            TurnSelected(origin, pos) => {
+                let modal = manager.select_facing(origin, pos, state);
+                // TODO
+                state.push_modal(modal, queue);
             }
             CancelSelected(pos, target) => {
-                state.grid.move_unit(target, pos);
+                manager.cancel_move(pos, target, state);
                 manager.move_cursor_to(pos, state);
                 manager.hide_cursor();
                 manager.select_unit(pos, state);
             }
+            FacingSelected(origin, pos) |
             TargetSelectorCanceled(origin, pos) => {
                 let modal = manager.handle_unit_moved(origin, pos, state);
                 // TODO
@@ -128,14 +295,30 @@ impl<'a> Behavior<State<'a>> for Scene {
                 // TODO
                 state.push_modal(modal, queue);
             }
-            TargetConfirmed(pos, target) => manager.target_confirmed(pos, target, state),
-            FinishTurn => {
-                manager.deselect();
-                for unit in state.grid.units_mut() {
-                    unit.spent = false;
+            OrderFollowed(_, to) => {
+                let (_, unit) = state.active_unit.take().expect("no active unit after move");
+                state.grid.add_unit(unit, to);
+                manager.grid_changed();
+            }
+            TargetConfirmed(origin, pos, target, weapon) => {
+                // The attacker may not survive the attack.
+                let facing = state.grid.unit(pos).map(|unit| unit.facing);
+                manager.target_confirmed(pos, target, weapon, state);
+                state.actions.push(Action::Attack {
+                    from: origin,
+                    to: pos,
+                    facing: facing,
+                    target: target,
+                    weapon: weapon,
+                });
+            }
+            GoTo => manager.toggle_go_to(state),
+            ToggleThreats => manager.toggle_threats(),
+            SaveGame => {
+                match save::save_game(&state.config.save_path, state) {
+                    Ok(()) => info!("Saved game to {}", state.config.save_path.display()),
+                    Err(err) => error!("Could not save game: {}", err),
                 }
-                state.turn_info.end_turn();
-                // TODO: Display a turn change animation here
             }
 
             MouseMovedTo(x, y) => manager.mouse_moved_to(x, y, state),
@@ -157,7 +340,7 @@ impl<'a> Behavior<State<'a>> for Scene {
 
     fn render(&mut self, state: &State<'a>, renderer: &mut Renderer) {
         self.grid_manager.render(state, renderer);
-        self.info_box.render(state, renderer);
+        self.info_box.render(state, self.grid_manager.cursor(), renderer);
         if let Some(modal) = self.modal_stack.last_mut() {
             modal.render(state, renderer);
         };
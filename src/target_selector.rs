@@ -1,24 +1,61 @@
-use glorious::{Behavior, Renderer, Sprite};
+use glorious::{Behavior, Color, Label, Renderer, Sprite};
+use sdl2::rect::Rect;
 
 use common::{Message, State};
+use info::RangeKind;
 use resources::CROSSHAIR_PATH;
 
+/// The opacity of the tint on tiles hit by an area attack at full
+/// strength; weaker hits are more transparent.
+const BLAST_ALPHA: u32 = 153;
+
+const COLOR_FORECAST: Color = Color(0xff, 0xff, 0xff, 0xff);
+const COLOR_FORECAST_BACKGROUND: Color = Color(0x00, 0x00, 0x00, 0xaa);
+
 #[derive(Debug)]
 pub struct TargetSelector {
     pos: (u32, u32),
     origin: (u32, u32),
+    weapon: usize,
     selected: usize,
     targets: Vec<(u32, u32)>,
+    /// What attacking each target would do, if it is a unit.
+    forecasts: Vec<Option<Label>>,
 }
 
 impl TargetSelector {
-    pub fn new(pos: (u32, u32), origin: (u32, u32), targets: Vec<(u32, u32)>) -> TargetSelector {
+    pub fn new(pos: (u32, u32),
+               origin: (u32, u32),
+               weapon: usize,
+               targets: Vec<(u32, u32)>,
+               state: &State)
+               -> TargetSelector {
         assert!(!targets.is_empty(), "No targets given to selector");
+        let forecasts = targets.iter()
+            .map(|&target| {
+                state.grid.forecast(pos, target, weapon).map(|forecast| {
+                    let mut text = if forecast.min == forecast.max {
+                        format!("{}", forecast.min)
+                    } else {
+                        format!("{}\u{2013}{}", forecast.min, forecast.max)
+                    };
+                    if forecast.kill_chance > 0 {
+                        text.push_str(&format!(", {}% kill", forecast.kill_chance));
+                    }
+                    Label::new(state.health_label_font,
+                               &text,
+                               COLOR_FORECAST,
+                               state.resources.device())
+                })
+            })
+            .collect();
         TargetSelector {
             pos: pos,
             origin: origin,
+            weapon: weapon,
             selected: 0,
             targets: targets,
+            forecasts: forecasts,
         }
     }
 
@@ -28,7 +65,7 @@ impl TargetSelector {
         let selected = self.targets[self.selected];
         debug!("Attacking target at {:?}", selected);
         state.break_modal(queue);
-        queue.push(TargetConfirmed(self.pos, selected));
+        queue.push(TargetConfirmed(self.origin, self.pos, selected, self.weapon));
         queue.push(UnitSpent(self.pos));
     }
 
@@ -91,8 +128,28 @@ impl<'a> Behavior<State<'a>> for TargetSelector {
     }
 
     fn render(&mut self, state: &State<'a>, renderer: &mut Renderer) {
-        let rect = state.tile_rect(self.targets[self.selected]);
+        let target = self.targets[self.selected];
+        if let Some(unit) = state.grid.unit(self.pos) {
+            if let RangeKind::Area { .. } = unit.kind.weapons[self.weapon].range {
+                for (pos, share) in state.grid.blast_area(unit, self.weapon, target) {
+                    let alpha = BLAST_ALPHA * share / 100;
+                    renderer.set_draw_color(Color(0xff, 0x88, 0x22, alpha as u8));
+                    renderer.fill_rect(state.tile_rect(pos)).unwrap();
+                }
+            }
+        }
+
+        let rect = state.tile_rect(target);
         let sprite = Sprite::new(state.resources.texture(CROSSHAIR_PATH), None);
         sprite.render_rect(renderer, rect);
+
+        // Damage and chance to kill, just below the target.
+        if let Some(ref label) = self.forecasts[self.selected] {
+            let (w, h) = label.size();
+            let (x, y) = (rect.x(), rect.y() + rect.height() as i32);
+            renderer.set_draw_color(COLOR_FORECAST_BACKGROUND);
+            renderer.fill_rect(Rect::new(x, y, w + 4, h)).unwrap();
+            label.render(renderer, x + 2, y);
+        }
     }
 }
@@ -6,6 +6,8 @@ pub struct Tile {
     pub terrain: Terrain,
     pub faction: Option<Faction>,
     pub capture: Option<(Faction, u32)>,
+    /// How high the tile is, in levels above the lowest ground.
+    pub elevation: u32,
 }
 
 impl Tile {
@@ -0,0 +1,278 @@
+use std::cmp;
+
+/// How tiles are laid out, in level coordinates.
+///
+/// Hex maps use "odd-r" offset coordinates: rows are stored like those of
+/// a square grid, and every odd row is shifted half a tile to the right.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Topology {
+    Square,
+    Hex,
+}
+
+const SQUARE_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+// East, north-east, north-west, west, south-west and south-east.
+const HEX_DIRECTIONS_EVEN: [(i32, i32); 6] = [(1, 0), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1)];
+const HEX_DIRECTIONS_ODD: [(i32, i32); 6] = [(1, 0), (1, 1), (0, 1), (-1, 0), (0, -1), (1, -1)];
+
+const SQUARE_DIRECTION_NAMES: [&'static str; 4] = ["East", "North", "West", "South"];
+const HEX_DIRECTION_NAMES: [&'static str; 6] =
+    ["East", "North-east", "North-west", "West", "South-west", "South-east"];
+
+/// How distances are measured on square grids, for ranged attacks.
+///
+/// Hex grids have only one sensible distance, so they ignore the metric.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// Steps along the axes, which makes a diamond.
+    Manhattan,
+    /// Steps in any of the eight directions, which makes a square.
+    Chebyshev,
+    /// Straight line distance rounded up, which makes a circle.
+    Euclidean,
+}
+
+impl Metric {
+    #[inline]
+    pub fn from_name(name: &str) -> Option<Metric> {
+        match name {
+            "manhattan" => Some(Metric::Manhattan),
+            "chebyshev" => Some(Metric::Chebyshev),
+            "euclidean" => Some(Metric::Euclidean),
+            _ => None,
+        }
+    }
+
+    /// Returns the length of the offset `(dx, dy)`.
+    pub fn length(&self, (dx, dy): (i32, i32)) -> u32 {
+        let (dx, dy) = (dx.abs() as u32, dy.abs() as u32);
+        match *self {
+            Metric::Manhattan => dx + dy,
+            Metric::Chebyshev => cmp::max(dx, dy),
+            Metric::Euclidean => {
+                // The smallest length whose square is at least that of the offset.
+                let square = dx * dx + dy * dy;
+                let mut length = cmp::max(dx, dy);
+                while length * length < square {
+                    length += 1;
+                }
+                length
+            }
+        }
+    }
+}
+
+/// Which edges of a map lead around to the opposite ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wrap {
+    None,
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+impl Wrap {
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Wrap::None => "none",
+            Wrap::Horizontal => "horizontal",
+            Wrap::Vertical => "vertical",
+            Wrap::Both => "both",
+        }
+    }
+
+    #[inline]
+    pub fn from_name(name: &str) -> Option<Wrap> {
+        match name {
+            "none" => Some(Wrap::None),
+            "horizontal" => Some(Wrap::Horizontal),
+            "vertical" => Some(Wrap::Vertical),
+            "both" => Some(Wrap::Both),
+            _ => None,
+        }
+    }
+
+    /// Whether leaving the map on the left or right brings a unit back on
+    /// the other side.
+    #[inline]
+    pub fn horizontal(&self) -> bool {
+        *self == Wrap::Horizontal || *self == Wrap::Both
+    }
+
+    /// Whether leaving the map at the top or bottom brings a unit back on
+    /// the other side.
+    #[inline]
+    pub fn vertical(&self) -> bool {
+        *self == Wrap::Vertical || *self == Wrap::Both
+    }
+}
+
+/// Converts odd-r offset coordinates to cube coordinates (without the
+/// redundant third one).
+#[inline]
+fn to_cube((x, y): (i32, i32)) -> (i32, i32) {
+    (x - (y - (y & 1)) / 2, y)
+}
+
+#[inline]
+fn from_cube((q, r): (i32, i32)) -> (i32, i32) {
+    (q + (r - (r & 1)) / 2, r)
+}
+
+impl Topology {
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Topology::Square => "square",
+            Topology::Hex => "hex",
+        }
+    }
+
+    #[inline]
+    pub fn from_name(name: &str) -> Option<Topology> {
+        match name {
+            "square" => Some(Topology::Square),
+            "hex" => Some(Topology::Hex),
+            _ => None,
+        }
+    }
+
+    /// The number of neighbors of each tile.
+    #[inline]
+    pub fn direction_count(&self) -> usize {
+        match *self {
+            Topology::Square => SQUARE_DIRECTIONS.len(),
+            Topology::Hex => HEX_DIRECTIONS_EVEN.len(),
+        }
+    }
+
+    #[inline]
+    pub fn direction_name(&self, dir: usize) -> &'static str {
+        match *self {
+            Topology::Square => SQUARE_DIRECTION_NAMES[dir],
+            Topology::Hex => HEX_DIRECTION_NAMES[dir],
+        }
+    }
+
+    #[inline]
+    pub fn opposite(&self, dir: usize) -> usize {
+        (dir + self.direction_count() / 2) % self.direction_count()
+    }
+
+    /// Returns the neighbor of `pos` in direction `dir`, counting
+    /// counter-clockwise from east.
+    #[inline]
+    pub fn neighbor(&self, pos: (i32, i32), dir: usize) -> (i32, i32) {
+        let (dx, dy) = match *self {
+            Topology::Square => SQUARE_DIRECTIONS[dir],
+            Topology::Hex if pos.1 & 1 == 0 => HEX_DIRECTIONS_EVEN[dir],
+            Topology::Hex => HEX_DIRECTIONS_ODD[dir],
+        };
+        (pos.0 + dx, pos.1 + dy)
+    }
+
+    /// Returns the direction from one tile to an adjacent one.
+    pub fn direction(&self, from: (i32, i32), to: (i32, i32)) -> Option<usize> {
+        (0..self.direction_count()).find(|&dir| self.neighbor(from, dir) == to)
+    }
+
+    /// Returns the number of steps between two tiles.
+    pub fn distance(&self, a: (i32, i32), b: (i32, i32)) -> u32 {
+        match *self {
+            Topology::Square => ((a.0 - b.0).abs() + (a.1 - b.1).abs()) as u32,
+            Topology::Hex => {
+                let (aq, ar) = to_cube(a);
+                let (bq, br) = to_cube(b);
+                let (dq, dr) = (aq - bq, ar - br);
+                ((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as u32
+            }
+        }
+    }
+
+    /// Returns the distance between two tiles as measured by `metric`.
+    pub fn measure(&self, a: (i32, i32), b: (i32, i32), metric: Metric) -> u32 {
+        match *self {
+            Topology::Square => metric.length((a.0 - b.0, a.1 - b.1)),
+            Topology::Hex => self.distance(a, b),
+        }
+    }
+
+    /// Returns every tile exactly `radius` from `center`, as measured by
+    /// `metric`.
+    pub fn ring(&self, center: (i32, i32), radius: u32, metric: Metric) -> Vec<(i32, i32)> {
+        let r = radius as i32;
+        let mut ring = Vec::new();
+        for y in center.1 - r..center.1 + r + 1 {
+            for x in center.0 - r - 1..center.0 + r + 2 {
+                if self.measure(center, (x, y), metric) == radius {
+                    ring.push((x, y));
+                }
+            }
+        }
+        ring
+    }
+
+    /// Returns the tiles on the straight line between two tiles, each
+    /// adjacent to the last, including both ends.
+    pub fn line(&self, from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+        let n = self.distance(from, to);
+        if n == 0 {
+            return vec![from];
+        }
+        match *self {
+            Topology::Square => {
+                // Step along whichever axis is further behind the line.
+                let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+                let (nx, ny) = (dx.abs(), dy.abs());
+                let (mut ix, mut iy) = (0, 0);
+                let mut pos = from;
+                let mut line = vec![from];
+                while ix < nx || iy < ny {
+                    if (1 + 2 * ix) * ny <= (1 + 2 * iy) * nx {
+                        pos.0 += dx.signum();
+                        ix += 1;
+                    } else {
+                        pos.1 += dy.signum();
+                        iy += 1;
+                    }
+                    line.push(pos);
+                }
+                line
+            }
+            Topology::Hex => {
+                let (aq, ar) = to_cube(from);
+                let (bq, br) = to_cube(to);
+                // Nudged so that lines along edges consistently pick a side.
+                let (aq, ar) = (aq as f64 + 1e-6, ar as f64 + 1e-6);
+                let (bq, br) = (bq as f64 + 1e-6, br as f64 + 1e-6);
+                (0..n + 1)
+                    .map(|i| {
+                        let t = i as f64 / n as f64;
+                        let q = aq + (bq - aq) * t;
+                        let r = ar + (br - ar) * t;
+                        let s = -q - r;
+                        let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+                        let (eq, er, es) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+                        if eq > er && eq > es {
+                            rq = -rr - rs;
+                        } else if er > es {
+                            rr = -rq - rs;
+                        }
+                        from_cube((rq as i32, rr as i32))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Returns how far from its place on a square grid a tile is drawn.
+    #[inline]
+    pub fn project(&self, pos: (i32, i32), tile_size: (u32, u32)) -> (i32, i32) {
+        match *self {
+            Topology::Hex if pos.1 & 1 != 0 => (tile_size.0 as i32 / 2, 0),
+            _ => (0, 0),
+        }
+    }
+}
@@ -0,0 +1,490 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+use json;
+use spec::TurnFileSpec;
+
+use action::Action;
+use common::{DurationExt, TurnInfo};
+use faction::Faction;
+use grid::Grid;
+use load::load_json;
+
+const POLL_INTERVAL_MS: u64 = 1000;
+
+/// The version of the turn file format, raised whenever the state hash
+/// or the meaning of actions changes, since older files cannot then be
+/// replayed. Files without a version are from version 1, which came
+/// before units had facings and go-to orders were actions.
+const TURN_FILE_VERSION: u32 = 2;
+
+/// A 64-bit FNV-1a hasher.
+///
+/// `std::hash` makes no promises about stability across platforms or
+/// compiler versions, which turn files need.
+struct StateHasher(u64);
+
+impl StateHasher {
+    #[inline]
+    fn new() -> StateHasher {
+        StateHasher(0xcbf29ce484222325)
+    }
+
+    #[inline]
+    fn write_u8(&mut self, byte: u8) {
+        self.0 ^= byte as u64;
+        self.0 = self.0.wrapping_mul(0x100000001b3);
+    }
+
+    #[inline]
+    fn write_u32(&mut self, value: u32) {
+        for i in 0..4 {
+            self.write_u8((value >> (i * 8)) as u8);
+        }
+    }
+
+    #[inline]
+    fn write_str(&mut self, value: &str) {
+        self.write_u32(value.len() as u32);
+        for &byte in value.as_bytes() {
+            self.write_u8(byte);
+        }
+    }
+
+    #[inline]
+    fn write_faction(&mut self, faction: Option<Faction>) {
+        self.write_str(faction.map_or("", |f| f.name()));
+    }
+}
+
+/// Hashes everything about the game state that actions can change.
+pub fn state_hash(grid: &Grid, turn_info: &TurnInfo) -> u64 {
+    let mut hasher = StateHasher::new();
+    hasher.write_u32(turn_info.turn());
+    hasher.write_faction(Some(turn_info.current_faction()));
+    hasher.write_u32(turn_info.actions_left());
+
+    let (w, h) = grid.size();
+    hasher.write_u32(w);
+    hasher.write_u32(h);
+    for y in 0..h {
+        for x in 0..w {
+            let (unit, tile) = grid.unit_and_tile((x, y));
+            hasher.write_str(&tile.terrain.name);
+            hasher.write_faction(tile.faction);
+            match tile.capture {
+                None => hasher.write_u8(0),
+                Some((faction, value)) => {
+                    hasher.write_u8(1);
+                    hasher.write_faction(Some(faction));
+                    hasher.write_u32(value);
+                }
+            }
+            match unit {
+                None => hasher.write_u8(0),
+                Some(unit) => {
+                    hasher.write_u8(1);
+                    hasher.write_str(&unit.kind.name);
+                    hasher.write_faction(Some(unit.faction));
+                    hasher.write_u32(unit.health);
+                    hasher.write_u8(unit.spent as u8);
+                    hasher.write_u32(unit.facing as u32);
+                    match unit.goal {
+                        None => hasher.write_u8(0),
+                        Some((x, y)) => {
+                            hasher.write_u8(1);
+                            hasher.write_u32(x);
+                            hasher.write_u32(y);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    hasher.write_u32(grid.dice().state());
+    hasher.0
+}
+
+/// Returns the path of the turn file for the given turn.
+#[inline]
+pub fn turn_file_path(dir: &Path, turn: u32) -> PathBuf {
+    dir.join(format!("turn_{:04}.json", turn))
+}
+
+/// A record of a single turn, which can be replayed on another machine.
+///
+/// The state hashes catch files that do not fit the game they are
+/// replayed in: files played from another state, out of order, or
+/// corrupted on the way. They are not signed, however, so they do not
+/// stop a player who edits a file and works out the hashes again.
+#[derive(Clone, Debug)]
+pub struct TurnFile {
+    pub turn: u32,
+    pub faction: Faction,
+    pub state_before: u64,
+    pub actions: Vec<Action>,
+    pub state_after: u64,
+}
+
+impl TurnFile {
+    pub fn from_spec(spec: TurnFileSpec) -> Result<TurnFile, String> {
+        let version = spec.version.unwrap_or(1);
+        if version != TURN_FILE_VERSION {
+            return Err(format!("turn file is version {}, but this game only plays version {}",
+                               version,
+                               TURN_FILE_VERSION));
+        }
+        let faction = Faction::from_name(&spec.faction)
+            .ok_or_else(|| format!("unrecognized faction {:?}", spec.faction))?;
+        let actions = spec.actions
+            .into_iter()
+            .map(Action::from_spec)
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(TurnFile {
+            turn: spec.turn,
+            faction: faction,
+            state_before: spec.state_before,
+            actions: actions,
+            state_after: spec.state_after,
+        })
+    }
+
+    pub fn to_spec(&self) -> TurnFileSpec {
+        TurnFileSpec {
+            version: Some(TURN_FILE_VERSION),
+            turn: self.turn,
+            faction: self.faction.name().to_owned(),
+            state_before: self.state_before,
+            actions: self.actions.iter().map(Action::to_spec).collect(),
+            state_after: self.state_after,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<TurnFile, String> {
+        let spec = load_json(path).map_err(|err| format!("could not load turn file: {}", err))?;
+        TurnFile::from_spec(spec)
+    }
+
+    /// Saves the turn file, making sure that it is never seen half-written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("json.tmp");
+        {
+            let mut file = File::create(&tmp).map_err(|err| format!("{}", err))?;
+            json::to_writer_pretty(&mut file, &self.to_spec()).map_err(|err| format!("{}", err))?;
+        }
+        fs::rename(&tmp, path).map_err(|err| format!("{}", err))
+    }
+
+    /// Verifies the turn against the current state and replays it.
+    ///
+    /// Nothing is changed unless the whole turn could be verified.
+    pub fn replay(&self, grid: &mut Grid, turn_info: &mut TurnInfo) -> Result<(), String> {
+        if self.turn != turn_info.turn() {
+            return Err(format!("expected turn {}, but the file is for turn {}",
+                               turn_info.turn(),
+                               self.turn));
+        }
+        if self.faction != turn_info.current_faction() {
+            return Err(format!("expected a turn by {:?}, but the file is by {:?}",
+                               turn_info.current_faction(),
+                               self.faction));
+        }
+        if self.state_before != state_hash(grid, turn_info) {
+            return Err("the turn was not played from the current game state".to_owned());
+        }
+
+        let mut new_grid = grid.clone();
+        let mut new_turn_info = turn_info.clone();
+        for (i, action) in self.actions.iter().enumerate() {
+            action.apply(&mut new_grid, &mut new_turn_info)
+                .map_err(|err| format!("invalid action #{}: {}", i + 1, err))?;
+            new_turn_info.remove_defeated(&new_grid);
+        }
+        new_grid.ready_units();
+        new_turn_info.end_turn();
+
+        if self.state_after != state_hash(&new_grid, &new_turn_info) {
+            return Err("the game state after replaying the turn does not match".to_owned());
+        }
+        *grid = new_grid;
+        *turn_info = new_turn_info;
+        Ok(())
+    }
+}
+
+/// Exchanges turn files with other players through a shared directory.
+#[derive(Debug)]
+pub struct PlayByEmail {
+    dir: PathBuf,
+    local: Faction,
+    state_before: u64,
+    waiting: bool,
+    prev_poll_time: Instant,
+    rejected: Option<SystemTime>,
+}
+
+impl PlayByEmail {
+    /// Replays every turn file already in `dir`, and then waits for the
+    /// other players unless it is the turn of `local`, the faction played
+    /// on this machine.
+    pub fn catch_up(dir: PathBuf,
+                    local: Faction,
+                    grid: &mut Grid,
+                    turn_info: &mut TurnInfo)
+                    -> Result<PlayByEmail, String> {
+        loop {
+            let path = turn_file_path(&dir, turn_info.turn());
+            if !path.exists() {
+                break;
+            }
+            TurnFile::load(&path)
+                .and_then(|file| file.replay(grid, turn_info))
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            info!("Replayed {}", path.display());
+        }
+        info!("Playing by email as {:?}", local);
+        Ok(PlayByEmail {
+            dir: dir,
+            local: local,
+            state_before: state_hash(grid, turn_info),
+            waiting: turn_info.current_faction() != local,
+            prev_poll_time: Instant::now(),
+            rejected: None,
+        })
+    }
+
+    /// Returns whether another player is taking their turn.
+    #[inline]
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    /// Writes the file for the turn that was just ended.
+    pub fn turn_finished(&mut self,
+                         turn: u32,
+                         faction: Faction,
+                         actions: Vec<Action>,
+                         grid: &Grid,
+                         turn_info: &TurnInfo)
+                         -> Result<(), String> {
+        if faction != self.local {
+            return Err(format!("{:?} is played on another machine", faction));
+        }
+        let file = TurnFile {
+            turn: turn,
+            faction: faction,
+            state_before: self.state_before,
+            actions: actions,
+            state_after: state_hash(grid, turn_info),
+        };
+        self.state_before = file.state_after;
+        self.waiting = turn_info.current_faction() != self.local;
+
+        let path = turn_file_path(&self.dir, turn);
+        file.save(&path)?;
+        info!("Wrote {}", path.display());
+        Ok(())
+    }
+
+    /// Checks for the next turn file while waiting, and replays it.
+    /// Returns whether a turn was replayed.
+    pub fn poll(&mut self, grid: &mut Grid, turn_info: &mut TurnInfo) -> bool {
+        if !self.waiting {
+            return false;
+        }
+        let now = Instant::now();
+        if now.duration_since(self.prev_poll_time).as_millis() < POLL_INTERVAL_MS {
+            return false;
+        }
+        self.prev_poll_time = now;
+
+        let path = turn_file_path(&self.dir, turn_info.turn());
+        let modified = match fs::metadata(&path).and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(_) => return false,
+        };
+        if self.rejected == Some(modified) {
+            return false;
+        }
+        match TurnFile::load(&path).and_then(|file| file.replay(grid, turn_info)) {
+            Ok(()) => {
+                info!("Replayed {}", path.display());
+                self.rejected = None;
+                self.state_before = state_hash(grid, turn_info);
+                self.waiting = turn_info.current_faction() != self.local;
+                true
+            }
+            Err(err) => {
+                error!("Rejected {}: {}", path.display(), err);
+                self.rejected = Some(modified);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use action::Action;
+    use common::TurnInfo;
+    use faction::Faction;
+    use fixtures;
+    use grid::Grid;
+
+    use super::{state_hash, TurnFile};
+
+    fn new_game() -> (Grid, TurnInfo) {
+        let grid = fixtures::grid(&["....", "....", "...."],
+                                  &[("warrior", (0, 0), 1), ("warrior", (3, 2), 2)]);
+        (grid, TurnInfo::new(vec![Faction::Red, Faction::Blue], 4))
+    }
+
+    #[inline]
+    fn wait(from: (u32, u32), to: (u32, u32)) -> Action {
+        Action::Wait {
+            from: from,
+            to: to,
+            facing: None,
+        }
+    }
+
+    /// Plays a turn the way the game does, and returns its turn file.
+    fn play(grid: &mut Grid, turn_info: &mut TurnInfo, actions: Vec<Action>) -> TurnFile {
+        let turn = turn_info.turn();
+        let faction = turn_info.current_faction();
+        let state_before = state_hash(grid, turn_info);
+        for action in &actions {
+            action.apply(grid, turn_info).expect("illegal action");
+        }
+        grid.ready_units();
+        turn_info.end_turn();
+        TurnFile {
+            turn: turn,
+            faction: faction,
+            state_before: state_before,
+            actions: actions,
+            state_after: state_hash(grid, turn_info),
+        }
+    }
+
+    /// Checks that replaying `file` fails and leaves the game as it was.
+    fn assert_rejected(file: &TurnFile, grid: &mut Grid, turn_info: &mut TurnInfo) {
+        let before = state_hash(grid, turn_info);
+        assert!(file.replay(grid, turn_info).is_err());
+        assert_eq!(state_hash(grid, turn_info), before);
+    }
+
+    #[test]
+    fn replays_turns_in_order() {
+        let (mut grid, mut turn_info) = new_game();
+        let first = play(&mut grid, &mut turn_info, vec![wait((0, 0), (2, 0))]);
+        let second = play(&mut grid, &mut turn_info, vec![wait((3, 2), (3, 1))]);
+
+        let (mut other_grid, mut other_turn_info) = new_game();
+        first.replay(&mut other_grid, &mut other_turn_info).unwrap();
+        second.replay(&mut other_grid, &mut other_turn_info).unwrap();
+        assert_eq!(state_hash(&other_grid, &other_turn_info),
+                   state_hash(&grid, &turn_info));
+    }
+
+    #[test]
+    fn replays_go_to_orders() {
+        let order = Action::Order {
+            at: (0, 0),
+            goal: Some((3, 0)),
+        };
+        let (mut grid, mut turn_info) = new_game();
+        let file = play(&mut grid, &mut turn_info, vec![order, wait((0, 0), (2, 0))]);
+        assert_eq!(grid.unit((2, 0)).unwrap().goal, Some((3, 0)));
+
+        let (mut other_grid, mut other_turn_info) = new_game();
+        file.replay(&mut other_grid, &mut other_turn_info).unwrap();
+        assert_eq!(state_hash(&other_grid, &other_turn_info),
+                   state_hash(&grid, &turn_info));
+
+        // Without the order, the unit would have no goal.
+        let mut modified = file.clone();
+        modified.actions.remove(0);
+        let (mut grid, mut turn_info) = new_game();
+        assert_rejected(&modified, &mut grid, &mut turn_info);
+    }
+
+    #[test]
+    fn rejects_old_versions() {
+        let (mut grid, mut turn_info) = new_game();
+        let file = play(&mut grid, &mut turn_info, vec![wait((0, 0), (2, 0))]);
+        assert!(TurnFile::from_spec(file.to_spec()).is_ok());
+
+        let mut spec = file.to_spec();
+        spec.version = None;
+        assert!(TurnFile::from_spec(spec).is_err());
+    }
+
+    #[test]
+    fn rejects_modified_action() {
+        let (mut grid, mut turn_info) = new_game();
+        let mut file = play(&mut grid, &mut turn_info, vec![wait((0, 0), (2, 0))]);
+        // Still a legal move, but not the one the hash was made for.
+        file.actions[0] = wait((0, 0), (1, 1));
+
+        let (mut grid, mut turn_info) = new_game();
+        assert_rejected(&file, &mut grid, &mut turn_info);
+    }
+
+    #[test]
+    fn rejects_illegal_action() {
+        let (mut grid, mut turn_info) = new_game();
+        let mut file = play(&mut grid, &mut turn_info, vec![wait((0, 0), (2, 0))]);
+        file.actions.push(wait((3, 2), (3, 1)));
+
+        let (mut grid, mut turn_info) = new_game();
+        assert_rejected(&file, &mut grid, &mut turn_info);
+    }
+
+    #[test]
+    fn rejects_modified_hashes() {
+        let (mut grid, mut turn_info) = new_game();
+        let file = play(&mut grid, &mut turn_info, vec![wait((0, 0), (2, 0))]);
+
+        let mut modified = file.clone();
+        modified.state_after ^= 1;
+        let (mut grid, mut turn_info) = new_game();
+        assert_rejected(&modified, &mut grid, &mut turn_info);
+
+        let mut modified = file.clone();
+        modified.state_before ^= 1;
+        assert_rejected(&modified, &mut grid, &mut turn_info);
+    }
+
+    #[test]
+    fn rejects_skipped_turn() {
+        let (mut grid, mut turn_info) = new_game();
+        play(&mut grid, &mut turn_info, vec![wait((0, 0), (2, 0))]);
+        let second = play(&mut grid, &mut turn_info, vec![wait((3, 2), (3, 1))]);
+
+        let (mut grid, mut turn_info) = new_game();
+        assert_rejected(&second, &mut grid, &mut turn_info);
+    }
+
+    #[test]
+    fn rejects_duplicated_turn() {
+        let (mut grid, mut turn_info) = new_game();
+        let first = play(&mut grid, &mut turn_info, vec![wait((0, 0), (2, 0))]);
+
+        let (mut grid, mut turn_info) = new_game();
+        first.replay(&mut grid, &mut turn_info).unwrap();
+        assert_rejected(&first, &mut grid, &mut turn_info);
+    }
+
+    #[test]
+    fn rejects_turn_by_wrong_faction() {
+        let (mut grid, mut turn_info) = new_game();
+        let mut file = play(&mut grid, &mut turn_info, vec![wait((0, 0), (2, 0))]);
+        file.faction = Faction::Blue;
+
+        let (mut grid, mut turn_info) = new_game();
+        assert_rejected(&file, &mut grid, &mut turn_info);
+    }
+}
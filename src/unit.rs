@@ -1,7 +1,8 @@
+use std::cmp;
 use std::fmt::{self, Debug};
 
 use faction::Faction;
-use info::UnitKind;
+use info::{CombatInfo, DamageFormula, RangeKind, UnitKind};
 use info::Terrain;
 
 #[derive(Clone)]
@@ -10,6 +11,10 @@ pub struct Unit {
     pub faction: Faction,
     pub spent: bool,
     pub kind: UnitKind,
+    /// Where the unit has been ordered to go, over as many turns as it takes.
+    pub goal: Option<(u32, u32)>,
+    /// The direction the unit faces, as numbered by the topology.
+    pub facing: usize,
 }
 
 impl Unit {
@@ -19,36 +24,90 @@ impl Unit {
             faction: faction,
             spent: false,
             kind: kind,
+            goal: None,
+            facing: 0,
         }
     }
 
-    // TODO: Optimally we should not use floats here, but rather get a
-    // better idea of the units for the quantities.
+    // Combat is worked out in integers so that it comes out the same
+    // everywhere: damage in tenths of a health point, and bonuses and
+    // modifiers in percent.
 
-    pub fn defense_bonus(&self, terrain: &Terrain) -> f64 {
-        terrain.defense + self.kind.defense.defense
+    /// Returns the defense of the unit on `terrain`, in percent.
+    pub fn defense_bonus(&self, terrain: &Terrain) -> i32 {
+        let bonus = self.kind.terrain_bonuses.get(&terrain.name).map_or(0, |b| b.defense);
+        terrain.defense + self.kind.defense.defense + bonus
     }
 
-    pub fn attack_damage(&self, other: &Unit, terrain: &Terrain) -> f64 {
-        let def = other.defense_bonus(terrain);
-        let atk = self.kind.attack.damage *
-                  self.kind.attack.modifiers.get(&other.kind.defense.class).cloned().unwrap_or(1.0);
-        let atk_hp = self.health as f64 / 10.0;
-        let def_hp = other.health as f64 / 10.0;
-        atk * atk_hp * (1.0 - def * def_hp)
+    /// Returns how much more damage the unit deals from `terrain`, in
+    /// percent.
+    pub fn attack_bonus(&self, terrain: &Terrain) -> i32 {
+        self.kind.terrain_bonuses.get(&terrain.name).map_or(0, |b| b.attack)
     }
 
-    pub fn retaliation_damage(&self, _damage_taken: f64, other: &Unit, terrain: &Terrain) -> f64 {
-        self.attack_damage(other, terrain)
+    /// Returns the damage dealt from `terrain` to `other`, whose defense
+    /// is `defense` percent, with the weapon numbered `weapon`, in tenths
+    /// of a health point.
+    pub fn attack_damage(&self,
+                         weapon: usize,
+                         terrain: &Terrain,
+                         other: &Unit,
+                         defense: i32,
+                         formula: DamageFormula)
+                         -> u32 {
+        self.damage_with_health(self.health, weapon, terrain, other, defense, formula)
     }
 
-    pub fn receive_damage(&mut self, damage: f64) -> bool {
-        let damage = if damage < 0.0 {
-            0.0
+    /// Returns the damage dealt to `other` when striking back after losing
+    /// `health_lost` health points.
+    pub fn retaliation_damage(&self,
+                              weapon: usize,
+                              health_lost: u32,
+                              terrain: &Terrain,
+                              other: &Unit,
+                              defense: i32,
+                              rules: &CombatInfo)
+                              -> u32 {
+        let health = if rules.scale_retaliation {
+            self.health
         } else {
-            damage
+            self.health + health_lost
         };
-        self.health = self.health.saturating_sub(damage.round() as u32);
+        let damage = self.damage_with_health(health,
+                                             weapon,
+                                             terrain,
+                                             other,
+                                             defense,
+                                             rules.formula);
+        damage * rules.retaliation / 100
+    }
+
+    fn damage_with_health(&self,
+                          health: u32,
+                          weapon: usize,
+                          terrain: &Terrain,
+                          other: &Unit,
+                          defense: i32,
+                          formula: DamageFormula)
+                          -> u32 {
+        let attack = cmp::max(100 + self.attack_bonus(terrain), 0);
+        let weapon = &self.kind.weapons[weapon];
+        let modifier = weapon.modifiers.get(&other.kind.defense.class).cloned().unwrap_or(100);
+        let (atk_hp, def_hp) = match formula {
+            DamageFormula::Standard => (health, other.health),
+            DamageFormula::AttackerHealth => (health, 10),
+            DamageFormula::Flat => (10, 10),
+        };
+        // Both healths are out of 10, so the defense is in thousandths.
+        let def = 1000 - defense as i64 * def_hp as i64;
+        let damage = weapon.damage as i64 * attack as i64 * modifier as i64 * atk_hp as i64 * def;
+        cmp::max(damage / (100 * 100 * 10 * 1000), 0) as u32
+    }
+
+    /// Takes `damage` tenths of a health point, rounded to the nearest
+    /// point with halves rounded up, and returns whether the unit died.
+    pub fn receive_damage(&mut self, damage: u32) -> bool {
+        self.health = self.health.saturating_sub((damage + 5) / 10);
         self.health == 0
     }
 
@@ -62,9 +121,20 @@ impl Unit {
             .expect("missing terrain type in movement class")
     }
 
+    /// Whether the spear numbered `weapon` passes through `other`, after
+    /// already passing through `pierced` enemies.
     #[inline]
-    pub fn can_spear_through(&self, _other: &Unit) -> bool {
-        false
+    pub fn can_spear_through(&self, weapon: usize, other: &Unit, pierced: u32) -> bool {
+        match self.kind.weapons[weapon].range {
+            RangeKind::Spear { ref pierce, .. } => {
+                if self.can_attack(other) {
+                    pierced < pierce.enemies
+                } else {
+                    pierce.friendlies
+                }
+            }
+            _ => false,
+        }
     }
 
     #[inline]
@@ -86,7 +156,161 @@ impl Debug for Unit {
             .field("health", &self.health)
             .field("faction", &self.faction)
             .field("spent", &self.spent)
+            .field("goal", &self.goal)
+            .field("facing", &self.facing)
             .field("kind", &self.kind.name)
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use faction::Faction;
+    use fixtures;
+    use info::{DamageFormula, GameInfo};
+
+    use super::Unit;
+
+    const TERRAIN: [&'static str; 6] = ["default", "grass", "woods", "mountains", "city", "hq"];
+
+    fn unit(info: &GameInfo, kind: &str, faction: Faction) -> Unit {
+        Unit::new(info.unit_kinds[kind].clone(), faction)
+    }
+
+    /// Every weapon against every unit kind at full health, with both
+    /// units standing on the same kind of terrain, in tenths of a health
+    /// point: `damage * (1 + attack bonus) * modifier * (1 - defense)`,
+    /// rounded down.
+    #[test]
+    fn damage_at_full_health() {
+        let table = [
+            ("warrior", 0, "warrior", [16, 15, 14, 10, 8, 8]),
+            ("warrior", 0, "archer", [48, 45, 42, 30, 24, 24]),
+            ("warrior", 0, "defender", [10, 9, 8, 4, 2, 2]),
+            ("warrior", 0, "raccoon", [16, 15, 14, 10, 8, 8]),
+            ("warrior", 0, "catapult", [18, 17, 18, 12, 10, 10]),
+            ("archer", 0, "warrior", [32, 30, 28, 24, 16, 16]),
+            ("archer", 0, "archer", [32, 30, 28, 24, 16, 16]),
+            ("archer", 0, "defender", [24, 21, 19, 11, 4, 4]),
+            ("archer", 0, "raccoon", [32, 30, 28, 24, 16, 16]),
+            ("archer", 0, "catapult", [43, 40, 43, 34, 24, 24]),
+            ("archer", 1, "warrior", [8, 7, 7, 6, 4, 4]),
+            ("archer", 1, "archer", [8, 7, 7, 6, 4, 4]),
+            ("archer", 1, "defender", [5, 4, 4, 2, 1, 1]),
+            ("archer", 1, "raccoon", [8, 7, 7, 6, 4, 4]),
+            ("archer", 1, "catapult", [9, 8, 9, 7, 5, 5]),
+            ("defender", 0, "warrior", [16, 15, 14, 10, 8, 8]),
+            ("defender", 0, "archer", [16, 15, 14, 10, 8, 8]),
+            ("defender", 0, "defender", [10, 9, 8, 4, 2, 2]),
+            ("defender", 0, "raccoon", [16, 15, 14, 10, 8, 8]),
+            ("defender", 0, "catapult", [18, 17, 18, 12, 10, 10]),
+            ("raccoon", 0, "warrior", [32, 30, 28, 20, 16, 16]),
+            ("raccoon", 0, "archer", [32, 30, 28, 20, 16, 16]),
+            ("raccoon", 0, "defender", [20, 18, 16, 8, 4, 4]),
+            ("raccoon", 0, "raccoon", [32, 30, 28, 20, 16, 16]),
+            ("raccoon", 0, "catapult", [36, 34, 36, 24, 20, 20]),
+            ("catapult", 0, "warrior", [24, 22, 14, 15, 12, 12]),
+            ("catapult", 0, "archer", [24, 22, 14, 15, 12, 12]),
+            ("catapult", 0, "defender", [15, 13, 8, 6, 3, 3]),
+            ("catapult", 0, "raccoon", [24, 22, 14, 15, 12, 12]),
+            ("catapult", 0, "catapult", [27, 25, 18, 18, 15, 15]),
+        ];
+        let info = fixtures::info();
+        for &(kind, weapon, other, ref expected) in &table {
+            let attacker = unit(&info, kind, Faction::Red);
+            let defender = unit(&info, other, Faction::Blue);
+            for (name, &expected) in TERRAIN.iter().zip(expected.iter()) {
+                let terrain = &info.terrain[*name];
+                let defense = defender.defense_bonus(terrain);
+                let damage = attacker.attack_damage(weapon,
+                                                    terrain,
+                                                    &defender,
+                                                    defense,
+                                                    DamageFormula::Standard);
+                assert!(damage == expected,
+                        "{} with weapon {} against {} on {}: {} instead of {}",
+                        kind,
+                        weapon,
+                        other,
+                        name,
+                        damage,
+                        expected);
+            }
+        }
+    }
+
+    /// Checks every pair of healths against the formula in floating
+    /// point, as it was before combat went to integers.
+    #[test]
+    fn damage_scales_with_health() {
+        let info = fixtures::info();
+        let mut kinds = info.unit_kinds.keys().collect::<Vec<_>>();
+        kinds.sort();
+        for kind in &kinds {
+            let mut attacker = unit(&info, kind, Faction::Red);
+            for weapon in 0..attacker.kind.weapons.len() {
+                for other in &kinds {
+                    let mut defender = unit(&info, other, Faction::Blue);
+                    for name in &TERRAIN {
+                        let terrain = &info.terrain[*name];
+                        let defense = defender.defense_bonus(terrain);
+                        let attack = 100 + attacker.attack_bonus(terrain);
+                        let modifier = attacker.kind.weapons[weapon]
+                            .modifiers
+                            .get(&defender.kind.defense.class)
+                            .cloned()
+                            .unwrap_or(100);
+                        for atk_hp in 1..11 {
+                            for def_hp in 1..11 {
+                                attacker.health = atk_hp;
+                                defender.health = def_hp;
+                                let expected = attacker.kind.weapons[weapon].damage as f64 *
+                                               (attack as f64 / 100.0) *
+                                               (modifier as f64 / 100.0) *
+                                               (atk_hp as f64 / 10.0) *
+                                               (1.0 -
+                                                defense as f64 / 100.0 * def_hp as f64 / 10.0);
+                                let damage = attacker.attack_damage(weapon,
+                                                                    terrain,
+                                                                    &defender,
+                                                                    defense,
+                                                                    DamageFormula::Standard);
+                                assert_eq!(damage, (expected + 1e-9).floor() as u32);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn damage_formulas() {
+        let info = fixtures::info();
+        let mut attacker = unit(&info, "warrior", Faction::Red);
+        let mut defender = unit(&info, "warrior", Faction::Blue);
+        attacker.health = 5;
+        defender.health = 5;
+        let terrain = &info.terrain["default"];
+        let damage = |formula| attacker.attack_damage(0, terrain, &defender, 20, formula);
+        assert_eq!(damage(DamageFormula::Standard), 9);
+        assert_eq!(damage(DamageFormula::AttackerHealth), 8);
+        assert_eq!(damage(DamageFormula::Flat), 16);
+    }
+
+    #[test]
+    fn receive_damage_rounds_halves_up() {
+        let info = fixtures::info();
+        let mut defender = unit(&info, "warrior", Faction::Blue);
+        for &(damage, health) in &[(0, 10), (4, 10), (5, 9), (14, 9), (15, 8), (95, 0)] {
+            defender.health = 10;
+            assert_eq!(defender.receive_damage(damage), health == 0);
+            assert_eq!(defender.health, health);
+        }
+
+        defender.health = 1;
+        assert!(!defender.receive_damage(4));
+        assert!(defender.receive_damage(5));
+        assert_eq!(defender.health, 0);
+    }
+}
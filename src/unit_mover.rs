@@ -17,6 +17,9 @@ pub struct UnitMover {
     index: usize,
     delta: f32,
     start: Option<Instant>,
+    /// Whether the unit is following a go-to order, rather than moving
+    /// at the player's hand.
+    ordered: bool,
 }
 
 impl UnitMover {
@@ -29,9 +32,16 @@ impl UnitMover {
             index: 0,
             delta: 0.0,
             start: None,
+            ordered: false,
         }
     }
 
+    /// Shows a unit taking a step along the route to its go-to order.
+    #[inline]
+    pub fn following_order(unit: Unit, origin: (u32, u32), path: Vec<(u32, u32)>) -> UnitMover {
+        UnitMover { ordered: true, ..UnitMover::new(unit, origin, path) }
+    }
+
     fn current(&self) -> ((u32, u32), (u32, u32)) {
         if self.path.is_empty() {
             return (self.origin, self.origin);
@@ -59,11 +69,24 @@ impl<'a> Behavior<State<'a>> for UnitMover {
         let i = ms / MOVE_TILE_MS;
 
         if i >= self.path.len() as u64 {
-            let unit = self.unit.take().expect("missing unit");
+            let mut unit = self.unit.take().expect("missing unit");
             let to = *self.path.last().unwrap_or(&self.origin);
+            // The unit ends up facing the way it took its last step.
+            let last = if self.path.len() < 2 {
+                self.origin
+            } else {
+                self.path[self.path.len() - 2]
+            };
+            if let Some(facing) = state.grid.heading(last, to) {
+                unit.facing = facing;
+            }
             state.active_unit = Some((to, unit));
             state.pop_modal(queue);
-            queue.push(Message::UnitMoved(self.origin, to));
+            if self.ordered {
+                queue.push(Message::OrderFollowed(self.origin, to));
+            } else {
+                queue.push(Message::UnitMoved(self.origin, to));
+            }
         } else {
             self.index = i as usize;
             self.delta = (ms % MOVE_TILE_MS) as f32 / MOVE_TILE_MS as f32;
@@ -74,7 +97,7 @@ impl<'a> Behavior<State<'a>> for UnitMover {
         if let Some(ref unit) = self.unit {
             let (from, to) = self.current();
             let rect_a = state.tile_rect(from);
-            let rect_b = state.tile_rect(to);
+            let rect_b = state.tile_rect_from(from, to);
             let (w, h) = state.tile_size;
             let a = (rect_a.x(), rect_a.y());
             let b = (rect_b.x(), rect_b.y());